@@ -0,0 +1,129 @@
+//! Times full layout renders on a synthetic tileset, with uniform and mixed palette
+//! metatiles and with and without prewarmed atlases. Run with `cargo bench`.
+
+use frlg_render::map::Layout;
+use frlg_render::palette::Palette;
+use frlg_render::render::{render_layout, RenderOptions};
+use frlg_render::tileset::{GameVersion, LayoutTileset, Tileset};
+use std::time::{Duration, Instant};
+
+const TILES: u16 = 128;
+const METATILES: u16 = 512;
+const PALETTES: u16 = 13;
+const LAYOUT_DIMENSION: u32 = 128;
+const RUNS: u32 = 5;
+const METATILE_PASSES: u32 = 20;
+
+/// A 4bpp sheet of 16x8 tiles, each with its own pattern of palette indices.
+fn tiles_png() -> Vec<u8> {
+    let (width, height) = (16 * 8, u32::from(TILES / 16) * 8);
+    let pixels = (0..height)
+        .flat_map(|y| (0..width / 2).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let index = |x: u32| ((x * 3 + y * 5 + x / 8 * y / 8) % 16) as u8;
+            index(2 * x) << 4 | index(2 * x + 1)
+        })
+        .collect::<Vec<_>>();
+
+    let mut png_bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut png_bytes, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Four);
+    encoder.set_palette(vec![0; 16 * 3]);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&pixels)
+        .unwrap();
+    png_bytes
+}
+
+/// `palette(metatile, tile)` picks the palette slot of each tile of each metatile.
+fn layout_tileset(palette: impl Fn(u16, u16) -> u16) -> LayoutTileset {
+    let metatiles = (0..METATILES)
+        .flat_map(|metatile| (0..8).map(move |tile| (metatile, tile)))
+        .map(|(metatile, tile)| {
+            let tile_id = (metatile * 8 + tile) % TILES;
+            let flips = (metatile + tile) % 4;
+            tile_id | flips << 10 | palette(metatile, tile) << 12
+        })
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+    let palettes = (0..PALETTES)
+        .map(|slot| {
+            let shade = (slot * 16) as u8;
+            Palette::gradient((shade, 0, 255 - shade), (255, shade, 0))
+        })
+        .collect::<Vec<_>>();
+    let game = GameVersion::FireRedLeafGreen;
+    let tileset = Tileset::from_bytes(
+        &metatiles,
+        &vec![0; 4 * usize::from(METATILES)],
+        &tiles_png(),
+        palettes,
+        game,
+    )
+    .unwrap();
+    LayoutTileset::from_tilesets(tileset.clone(), tileset, game)
+}
+
+fn layout() -> Layout {
+    let cells = (0..LAYOUT_DIMENSION * LAYOUT_DIMENSION)
+        .map(|cell| (cell * 7 % u32::from(METATILES)) as u16)
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+    Layout::from_bytes(LAYOUT_DIMENSION, LAYOUT_DIMENSION, &cells, &[0; 8]).unwrap()
+}
+
+/// Renders `layout` from a freshly built tileset on every run, prewarming it first if asked,
+/// and prints the fastest and mean times.
+fn bench(name: &str, layout: &Layout, tileset: impl Fn() -> LayoutTileset, prewarm: bool) {
+    let options = RenderOptions::default();
+    let times = (0..RUNS)
+        .map(|_| {
+            let mut tileset = tileset();
+            let start = Instant::now();
+            if prewarm {
+                tileset.prewarm().unwrap();
+            }
+            render_layout(layout, &tileset, &options).unwrap();
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    let fastest = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / RUNS;
+    println!("{name:<24} fastest {fastest:>10.2?}  mean {mean:>10.2?}");
+}
+
+/// Draws every metatile of a freshly built tileset on every run, without laying out or
+/// scaling a whole layout, and prints the fastest and mean times.
+fn bench_metatiles(name: &str, tileset: impl Fn() -> LayoutTileset) {
+    let times = (0..RUNS)
+        .map(|_| {
+            let tileset = tileset();
+            let start = Instant::now();
+            for _ in 0..METATILE_PASSES {
+                for metatile_id in 0..METATILES {
+                    tileset.get_metatile_image_rgba(metatile_id).unwrap();
+                }
+            }
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    let fastest = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / RUNS;
+    println!("{name:<24} fastest {fastest:>10.2?}  mean {mean:>10.2?}");
+}
+
+fn main() {
+    let layout = layout();
+    let uniform = || layout_tileset(|metatile, _| metatile % PALETTES);
+    let mixed = || layout_tileset(|metatile, tile| (metatile + tile) % PALETTES);
+
+    bench("uniform", &layout, uniform, false);
+    bench("uniform, prewarmed", &layout, uniform, true);
+    bench("mixed", &layout, mixed, false);
+    bench("mixed, prewarmed", &layout, mixed, true);
+    bench_metatiles("uniform metatiles", uniform);
+    bench_metatiles("mixed metatiles", mixed);
+}
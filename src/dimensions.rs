@@ -0,0 +1,33 @@
+//! Pixel geometry shared by the tileset decoder and the renderer.
+//!
+//! A tile is an 8x8 block of 4bpp pixels. A metatile stacks two layers (bottom, then top),
+//! and each layer is a 2x2 grid of tiles, so a metatile is 16x16 pixels and references
+//! eight tiles in total. Tile references are stored layer by layer in row-major order.
+
+/// Width and height of a single tile, in pixels.
+pub const TILE_DIMENSION: u32 = 8;
+
+/// Number of tiles along each edge of a metatile layer.
+pub const METATILE_TILE_GRID: u32 = 2;
+
+/// Width and height of a metatile, in pixels (`TILE_DIMENSION * METATILE_TILE_GRID`).
+pub const METATILE_DIMENSION: u32 = TILE_DIMENSION * METATILE_TILE_GRID;
+
+/// Number of tiles making up one layer of a metatile.
+pub const TILES_PER_LAYER: usize = (METATILE_TILE_GRID * METATILE_TILE_GRID) as usize;
+
+/// Number of layers stored in each metatile.
+pub const METATILE_LAYERS: usize = 2;
+
+/// Number of tile references stored in each metatile.
+pub const TILES_PER_METATILE: usize = TILES_PER_LAYER * METATILE_LAYERS;
+
+/// Pixel coordinate `(x, y)` of the top-left corner of the metatile at `(row, col)` in a map.
+pub fn metatile_origin(row: u32, col: u32) -> (u32, u32) {
+    (col * METATILE_DIMENSION, row * METATILE_DIMENSION)
+}
+
+/// Index into a metatile's tile references for the tile at `(row, col)` of `layer`.
+pub fn metatile_tile_index(layer: usize, row: u32, col: u32) -> usize {
+    layer * TILES_PER_LAYER + (row * METATILE_TILE_GRID + col) as usize
+}
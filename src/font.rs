@@ -0,0 +1,126 @@
+//! A tiny built-in bitmap font for labelling rendered images.
+//!
+//! Glyphs are 3x5 pixels and are drawn with each glyph pixel scaled up to a square of
+//! `pixel_size` output pixels.
+
+use image::{ImageBuffer, Pixel};
+
+pub const GLYPH_WIDTH: u32 = 3;
+pub const GLYPH_HEIGHT: u32 = 5;
+const GLYPH_SPACING: u32 = 1;
+
+const DIGITS: [[u8; GLYPH_HEIGHT as usize]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+const LETTERS: [[u8; GLYPH_HEIGHT as usize]; 26] = [
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b110, 0b100, 0b111],
+    [0b111, 0b100, 0b110, 0b100, 0b100],
+    [0b011, 0b100, 0b101, 0b101, 0b011],
+    [0b101, 0b101, 0b111, 0b101, 0b101],
+    [0b111, 0b010, 0b010, 0b010, 0b111],
+    [0b001, 0b001, 0b001, 0b101, 0b010],
+    [0b101, 0b101, 0b110, 0b101, 0b101],
+    [0b100, 0b100, 0b100, 0b100, 0b111],
+    [0b101, 0b111, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b101, 0b101, 0b101],
+    [0b010, 0b101, 0b101, 0b101, 0b010],
+    [0b110, 0b101, 0b110, 0b100, 0b100],
+    [0b010, 0b101, 0b101, 0b110, 0b011],
+    [0b110, 0b101, 0b110, 0b101, 0b101],
+    [0b011, 0b100, 0b010, 0b001, 0b110],
+    [0b111, 0b010, 0b010, 0b010, 0b010],
+    [0b101, 0b101, 0b101, 0b101, 0b111],
+    [0b101, 0b101, 0b101, 0b101, 0b010],
+    [0b101, 0b101, 0b111, 0b111, 0b101],
+    [0b101, 0b101, 0b010, 0b101, 0b101],
+    [0b101, 0b101, 0b010, 0b010, 0b010],
+    [0b111, 0b001, 0b010, 0b100, 0b111],
+];
+const UNDERSCORE: [u8; GLYPH_HEIGHT as usize] = [0b000, 0b000, 0b000, 0b000, 0b111];
+const HYPHEN: [u8; GLYPH_HEIGHT as usize] = [0b000, 0b000, 0b111, 0b000, 0b000];
+
+/// Digits, letters (drawn in upper case), `_` and `-` have glyphs.
+fn glyph(c: char) -> Option<&'static [u8; GLYPH_HEIGHT as usize]> {
+    match c {
+        '0'..='9' => c.to_digit(10).map(|digit| &DIGITS[digit as usize]),
+        'a'..='z' | 'A'..='Z' => Some(&LETTERS[(c.to_ascii_uppercase() as u8 - b'A') as usize]),
+        '_' => Some(&UNDERSCORE),
+        '-' => Some(&HYPHEN),
+        _ => None,
+    }
+}
+
+/// Width in output pixels of `text` when drawn with [`draw_text`].
+pub fn text_width(text: &str, pixel_size: u32) -> u32 {
+    let glyphs = text.chars().count() as u32;
+    if glyphs == 0 {
+        0
+    } else {
+        (glyphs * (GLYPH_WIDTH + GLYPH_SPACING) - GLYPH_SPACING) * pixel_size
+    }
+}
+
+/// Height in output pixels of a line of text drawn with [`draw_text`].
+pub fn text_height(pixel_size: u32) -> u32 {
+    GLYPH_HEIGHT * pixel_size
+}
+
+/// Draws `text` with its top-left corner at `(x, y)`. Characters without a glyph are left
+/// blank and anything falling outside of `image` is clipped.
+pub fn draw_text<P: Pixel>(
+    image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    text: &str,
+    x: u32,
+    y: u32,
+    pixel_size: u32,
+    color: P,
+) {
+    for (idx, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let glyph_x = x + idx as u32 * (GLYPH_WIDTH + GLYPH_SPACING) * pixel_size;
+        for (glyph_row, bits) in rows.iter().enumerate() {
+            for glyph_col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - glyph_col)) == 0 {
+                    continue;
+                }
+                let left = glyph_x + glyph_col * pixel_size;
+                let top = y + glyph_row as u32 * pixel_size;
+                fill_rect(image, left, top, pixel_size, pixel_size, color);
+            }
+        }
+    }
+}
+
+/// Fills a `width` x `height` rectangle at `(x, y)` with `color`, clipped to `image`.
+pub fn fill_rect<P: Pixel>(
+    image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    color: P,
+) {
+    let right = x.saturating_add(width).min(image.width());
+    let bottom = y.saturating_add(height).min(image.height());
+    for pixel_y in y..bottom {
+        for pixel_x in x..right {
+            image.put_pixel(pixel_x, pixel_y, color);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Transparent decompression of asset files which were stored gzip compressed.
+
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens `path` for reading, decompressing it on the fly if it starts with the gzip magic.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Reads the whole of `path`, decompressing it first if it's gzip compressed.
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let mut contents = vec![];
+    open(path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Reads the whole of `path` as UTF-8 text, decompressing it first if it's gzip compressed.
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut contents = String::new();
+    open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
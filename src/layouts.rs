@@ -0,0 +1,128 @@
+//! The table of layouts in a pret checkout's `data/layouts/layouts.json`.
+
+#[cfg(feature = "fs")]
+use crate::map;
+use serde::Deserialize;
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Every layout in a layouts table, parsed once and kept in file order.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Layouts {
+    //layouts_table_label: String,
+    layouts: Vec<LayoutEntry>,
+}
+
+/// A layout's metadata as listed in the layouts table.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LayoutEntry {
+    pub id: String,
+    pub width: u32,
+    pub height: u32,
+    pub primary_tileset: String,
+    pub secondary_tileset: String,
+    pub border_filepath: String,
+    pub blockdata_filepath: String,
+    pub border_width: Option<u32>,
+    pub border_height: Option<u32>,
+}
+
+impl Layouts {
+    /// Loads a `layouts.json` table. If the file doesn't exist, the per-layout
+    /// `<NAME>/layout.json` files in the same directory are loaded instead.
+    #[cfg(feature = "fs")]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match std::fs::File::open(path) {
+            Ok(file) => Self::from_reader(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match path.parent() {
+                Some(dir) if dir.is_dir() => {
+                    log::info!("{} not found, scanning {}", path.display(), dir.display());
+                    Self::load_dir(dir)
+                }
+                _ => Err(err),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Assembles a table from the `layout.json` file in each subdirectory of `dir`, as in
+    /// `data/layouts/<NAME>/layout.json`. Layouts are ordered by directory name.
+    #[cfg(feature = "fs")]
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut layout_files = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let layout_file = entry?.path().join("layout.json");
+            if layout_file.is_file() {
+                layout_files.push(layout_file);
+            }
+        }
+        layout_files.sort();
+
+        let layouts = layout_files
+            .into_iter()
+            .map(|layout_file| {
+                let file = std::fs::File::open(&layout_file)?;
+                serde_json::from_reader(file).map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {err}", layout_file.display()),
+                    )
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Layouts { layouts })
+    }
+
+    /// Parses a layouts table from the contents of a `layouts.json` file.
+    pub fn from_reader(reader: impl io::Read) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LayoutEntry> {
+        self.layouts.iter().find(|layout| layout.id == id)
+    }
+
+    /// Finds a layout by its id, or by its position in the table if `key` is a number.
+    pub fn find(&self, key: &str) -> Option<&LayoutEntry> {
+        match key.parse::<usize>() {
+            Ok(index) => self.layouts.get(index),
+            Err(_) => self.get(key),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LayoutEntry> {
+        self.layouts.iter()
+    }
+
+    /// Every layout which uses the tileset symbol `name`, e.g. `gTileset_General`, as either
+    /// its primary or secondary tileset.
+    pub fn layouts_using_tileset(&self, name: &str) -> Vec<&LayoutEntry> {
+        self.layouts
+            .iter()
+            .filter(|layout| layout.primary_tileset == name || layout.secondary_tileset == name)
+            .collect()
+    }
+}
+
+impl LayoutEntry {
+    /// Loads the layout's blockdata and border, whose paths are relative to `pret_root`.
+    /// Uses the border size from the table when it's given and infers it otherwise.
+    #[cfg(feature = "fs")]
+    pub fn load(&self, pret_root: impl AsRef<Path>) -> io::Result<map::Layout> {
+        let blockdata_file = pret_root.as_ref().join(&self.blockdata_filepath);
+        let border_file = pret_root.as_ref().join(&self.border_filepath);
+        match (self.border_width, self.border_height) {
+            (Some(border_width), Some(border_height)) => map::Layout::load_with_border_size(
+                self.width,
+                self.height,
+                border_width,
+                border_height,
+                blockdata_file,
+                border_file,
+            ),
+            _ => map::Layout::load(self.width, self.height, blockdata_file, border_file),
+        }
+    }
+}
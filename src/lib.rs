@@ -1,5 +1,14 @@
+pub mod dimensions;
+pub mod font;
+#[cfg(feature = "fs")]
+mod gzip;
+pub mod layouts;
 pub mod map;
+pub mod overlay;
 pub mod palette;
+pub mod render;
+#[cfg(feature = "rom")]
+pub mod rom;
 pub mod tileset;
 
-pub const METATILE_DIMENSION: u32 = 16;
+pub use dimensions::METATILE_DIMENSION;
@@ -1,116 +1,1307 @@
-use clap::Parser;
+use base64::Engine;
+use clap::{Parser, Subcommand};
 use convert_case::Casing;
-use frlg_render::{map, tileset, METATILE_DIMENSION};
-use image::{GenericImage, ImageBuffer, RgbImage};
-use serde::Deserialize;
+use frlg_render::layouts::{LayoutEntry, Layouts};
+use frlg_render::{dimensions, map, overlay, render, tileset, METATILE_DIMENSION};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 const PRET_ROOT: &str = env!("PRET_ROOT");
 
 #[derive(Parser)]
 struct Args {
-    #[arg(long)]
-    /// The layout to render, e.g. LAYOUT_POWER_PLANT
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(long, global = true)]
+    /// The layout to render, e.g. LAYOUT_POWER_PLANT. A number picks the layout at that
+    /// position in the layouts table instead
     layout: Option<String>,
 
-    #[arg(short, long)]
-    /// The output path for the rendered png image, default is /tmp/render.png
+    #[arg(short, long, global = true)]
+    /// The output path for the rendered png image, default is /tmp/render.png. Use - to
+    /// write the png to stdout as base64
     output: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Render the luminance of each color instead of RGB, producing a grayscale png
+    grayscale: bool,
+
+    #[arg(long)]
+    /// Draw each pixel's palette index as a gray level instead of its color
+    raw_indices: bool,
+
+    #[arg(long)]
+    /// Label each impassable cell with its collision value
+    collision_labels: bool,
+
+    #[arg(long)]
+    /// Only draw the tiles beneath the player, hiding treetops, roofs and other foreground
+    bottom_only: bool,
+
+    #[arg(long)]
+    /// Draw the tiles above the player on bridge cells semi-transparently, hinting at the
+    /// ground beneath them
+    xray_bridges: bool,
+
+    #[arg(long)]
+    /// Show pixels no metatile layer draws as a gray checkerboard rather than the
+    /// background color
+    alpha_checker: bool,
+
+    #[arg(long, value_parser = parse_remap)]
+    /// Draw metatile TO wherever the layout uses metatile FROM, given as FROM=TO. Can be
+    /// repeated, and the blockdata isn't changed
+    remap: Vec<(u16, u16)>,
+
+    #[arg(long)]
+    /// Tint each cell with a color for its elevation
+    elevation: bool,
+
+    #[arg(long)]
+    /// Outline each impassable cell, leaving the rest of the map as drawn
+    wall_outlines: bool,
+
+    #[arg(long)]
+    /// Outline every 8x8 tile, with metatile boundaries drawn darker
+    tile_grid: bool,
+
+    #[arg(long)]
+    /// Add a key for color coded overlays beside the map
+    legend: bool,
+
+    #[arg(long, default_value_t = 1)]
+    /// Integer factor to upscale the output by
+    scale: u32,
+
+    #[arg(long)]
+    /// Only render part of the layout, given in metatiles as x,y,width,height
+    region: Option<render::Region>,
+
+    #[arg(long, value_parser = parse_pages, conflicts_with = "sidecar")]
+    /// Split the output into a grid of ROWS,COLS images named like out_0_1.png
+    tiles: Option<(u32, u32)>,
+
+    #[arg(long)]
+    /// Fill empty parts of the map and pixels no metatile layer draws with this color, given
+    /// as r,g,b, instead of black
+    background: Option<render::Color>,
+
+    #[arg(long)]
+    /// Print a report of what was loaded for the layout and its tilesets instead of rendering
+    inspect: bool,
+
+    #[arg(long)]
+    /// Print the files and directories the layout would be loaded from, then exit
+    print_paths: bool,
+
+    #[arg(long, value_parser = parse_neighbor)]
+    /// Draw a connected map faded around the edge, as DIRECTION=LAYOUT or
+    /// DIRECTION=LAYOUT:OFFSET with the offset in metatiles. Can be repeated
+    onion_skin: Vec<(render::Direction, String, i32)>,
+
+    #[arg(long, default_value_t = 4)]
+    /// Width in metatiles of the strip of each neighbor drawn by --onion-skin
+    onion_margin: u32,
+
+    #[arg(long, default_value_t = 96)]
+    /// Opacity of neighbors drawn by --onion-skin, out of 255
+    onion_opacity: u8,
+
+    #[arg(long)]
+    /// Trim edges made up entirely of the metatile most common along the map's border
+    autocrop: bool,
+
+    #[arg(long, global = true)]
+    /// Load the primary tileset from this directory instead of the layout's
+    primary_tileset: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    /// Load the secondary tileset from this directory instead of the layout's
+    secondary_tileset: Option<PathBuf>,
+
+    #[arg(long, global = true, default_value = "metatiles.bin")]
+    /// Name of the metatile file within each tileset directory
+    metatiles_file: PathBuf,
+
+    #[arg(long, global = true, default_value = "metatile_attributes.bin")]
+    /// Name of the metatile attributes file within each tileset directory
+    attributes_file: PathBuf,
+
+    #[arg(long, global = true, default_value = "tiles.png")]
+    /// Name of the tile sheet within each tileset directory
+    tiles_file: PathBuf,
+
+    #[arg(long, global = true)]
+    /// Read the primary tileset's palettes from this directory instead of its own
+    primary_palettes: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    /// Read the secondary tileset's palettes from this directory instead of its own
+    secondary_palettes: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    /// Diagnostic: draw every tile with this palette slot, ignoring the one each tile uses
+    force_palette: Option<usize>,
+
+    #[arg(long, global = true)]
+    /// Number of metatile ids reserved for the primary tileset, defaults to the game's
+    primary_metatiles: Option<usize>,
+
+    #[arg(long, global = true)]
+    /// Number of tile ids reserved for the primary tileset, defaults to the game's
+    primary_tiles: Option<usize>,
+
+    #[arg(long, global = true)]
+    /// Number of palette slots loaded from the primary tileset, defaults to the game's
+    primary_palette_count: Option<usize>,
+
+    #[arg(long)]
+    /// Also write a JSON file describing the pixel rectangle and contents of every cell
+    sidecar: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Also write a JSON file listing every cell which couldn't be drawn and why
+    report: Option<PathBuf>,
+
+    #[arg(long, global = true)]
+    /// Fail instead of rendering a partial image when a metatile, tile or palette is missing
+    strict: bool,
+
+    #[arg(long, global = true)]
+    /// Fail before rendering when any palette is malformed or entirely black, rather than
+    /// when a tile first uses it or not at all
+    strict_palettes: bool,
+
+    #[arg(long)]
+    /// Decode each tile sheet into per palette atlases before rendering. Speeds up large
+    /// renders with many mixed palette metatiles, at the cost of memory and loading every
+    /// palette up front
+    prewarm: bool,
+
+    #[arg(long, global = true)]
+    /// Only log warnings and errors, and finish a render with a one line summary
+    quiet: bool,
+
+    #[arg(long, global = true, default_value = "default")]
+    /// How hard to compress png output: fast, default or best. Fast trades file size for
+    /// encoding time, which adds up over large or batch renders
+    png_compression: render::PngCompression,
+
+    #[arg(long, global = true, default_value = "frlg")]
+    /// The game whose tileset format the assets use, either frlg or rse
+    game: tileset::GameVersion,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct LayoutsTable {
-    //layouts_table_label: String,
-    layouts: Vec<Layout>,
+#[derive(Subcommand)]
+enum Command {
+    /// Print usage statistics about the layout's tilesets instead of rendering
+    Stats,
+    /// Print how a single metatile is built from its attributes and tiles
+    DescribeMetatile {
+        #[arg(long)]
+        /// The metatile id as used by the layout, e.g. 640 for the first secondary metatile
+        id: u16,
+    },
+    /// Export one metatile zoomed in, with a grid and the palette index of every pixel
+    InspectMetatile {
+        #[arg(long)]
+        /// The metatile id as used by the layout
+        id: u16,
+
+        #[arg(long, default_value_t = 32)]
+        /// Output pixels per metatile pixel
+        zoom: u32,
+    },
+    /// Export one metatile as drawn, or with --sheet the tiles it's built from
+    DumpMetatile {
+        #[arg(long)]
+        /// The metatile id as used by the layout
+        id: u16,
+
+        #[arg(long, default_value_t = 8)]
+        /// Output pixels per metatile pixel
+        zoom: u32,
+
+        #[arg(long)]
+        /// Lay out the metatile's bottom and top tiles side by side, each labelled with its
+        /// tile id and palette number
+        sheet: bool,
+    },
+    /// Highlight every cell which differs from the cell a fixed offset away
+    SelfDiff {
+        #[arg(long, allow_hyphen_values = true, value_parser = parse_offset)]
+        /// The offset to compare each cell against, in metatiles as rows,cols
+        offset: (i64, i64),
+    },
+    /// List the layouts which use a tileset and would be affected by editing it
+    AffectedBy {
+        #[arg(long)]
+        /// The tileset symbol, e.g. gTileset_General
+        tileset: String,
+    },
+    /// Export the layout's collision values as an indexed png, one color per value
+    DumpCollision {
+        #[arg(long, default_value_t = 1)]
+        /// Output pixels along each edge of a cell
+        scale: u32,
+    },
+    /// Compare two versions of a tileset by the metatiles, tiles and palettes they draw
+    TilesetDiff {
+        #[arg(long)]
+        /// Directory of the original tileset
+        old: PathBuf,
+
+        #[arg(long)]
+        /// Directory of the changed tileset
+        new: PathBuf,
+    },
+    /// Render every layout listed in a json manifest, each with its own options. Failed
+    /// renders are reported at the end rather than stopping the batch
+    Batch {
+        #[arg(long)]
+        /// The manifest, whose output paths are relative to its own directory
+        manifest: PathBuf,
+    },
+    /// Render one metatile repeated to fill an area, e.g. to preview grass or water as a field
+    Fill {
+        #[arg(long)]
+        /// The metatile id as used by the layout
+        id: u16,
+
+        #[arg(long, value_parser = parse_size)]
+        /// The area to fill, in metatiles as WIDTHxHEIGHT
+        size: (u32, u32),
+    },
+    /// Render the layout at one pixel per metatile, each the metatile's average color
+    Overview,
+    /// Load and render every layout in strict mode, printing whether each passed. Fails if
+    /// any layout does, for use as a CI check
+    CheckAll {
+        #[arg(long)]
+        /// Only check each layout's data and the metatiles and tiles it references, skipping
+        /// the render
+        validate_only: bool,
+    },
+    /// Render thumbnails of several layouts into one labelled grid image. Layouts which
+    /// fail to load or render are left out with a warning
+    ContactSheet {
+        #[arg(long, value_delimiter = ',')]
+        /// Comma separated layout ids to include, defaults to every layout
+        layouts: Vec<String>,
+
+        #[arg(long, default_value_t = 128)]
+        /// Largest width or height of each thumbnail, in pixels
+        thumbnail_size: u32,
+
+        #[arg(long)]
+        /// Number of thumbnails per row, defaults to a roughly square grid
+        columns: Option<u32>,
+    },
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct Layout {
-    id: String,
+/// One cell of the rendered map as written to the sidecar file.
+#[derive(Debug, Serialize)]
+struct SidecarCell {
+    row: u32,
+    col: u32,
+    x: u32,
+    y: u32,
     width: u32,
     height: u32,
-    primary_tileset: String,
-    secondary_tileset: String,
-    border_filepath: String,
-    blockdata_filepath: String,
+    metatile_id: u16,
+    collision: u8,
+    elevation: u8,
+    behavior: Option<u16>,
+}
+
+/// A cell which couldn't be drawn, as written to the `--report` file.
+#[derive(Debug, Serialize)]
+struct ReportCell {
+    row: u32,
+    col: u32,
+    metatile_id: u16,
+    reason: String,
+}
+
+/// The file read by the `batch` command.
+#[derive(Debug, Deserialize)]
+struct BatchManifest {
+    renders: Vec<BatchRender>,
+}
+
+/// One render in a batch manifest. Options which aren't given take their defaults rather
+/// than the values passed on the command line.
+#[derive(Debug, Deserialize)]
+struct BatchRender {
+    layout: String,
+    output: PathBuf,
+    #[serde(default)]
+    scale: Option<u32>,
+    /// As `x,y,width,height`, like `--region`
+    #[serde(default)]
+    region: Option<String>,
+    #[serde(default)]
+    overlays: Vec<render::Overlay>,
+    #[serde(default)]
+    bottom_only: bool,
+    #[serde(default)]
+    grayscale: bool,
 }
 
+/// `--output` path which writes the image to stdout as base64 instead of to a file.
+const STDOUT: &str = "-";
+
 const LAYOUTS_FILE: &str = concat!(env!("PRET_ROOT"), "/data/layouts/layouts.json");
 
 fn main() -> io::Result<()> {
     env_logger::init();
 
     let args = Args::parse();
-    let map = args.layout.unwrap_or("LAYOUT_POWER_PLANT".to_string());
-    let output_file = args.output.unwrap_or(PathBuf::from("/tmp/render.png"));
+    if args.quiet {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    let map = args
+        .layout
+        .clone()
+        .unwrap_or("LAYOUT_POWER_PLANT".to_string());
+    let output_file = args
+        .output
+        .clone()
+        .unwrap_or(PathBuf::from("/tmp/render.png"));
 
-    let layouts = {
-        let file = File::open(LAYOUTS_FILE)?;
-        let layouts_table: LayoutsTable = serde_json::from_reader(file).unwrap();
-        layouts_table.layouts
-    };
+    let layouts = Layouts::load(LAYOUTS_FILE)?;
 
-    let Some(layout) = layouts
-        .into_iter()
-        .find(|layout| layout.id.as_str() == map.as_str())
-    else {
+    if let Some(Command::AffectedBy { tileset }) = &args.command {
+        for layout in layouts.layouts_using_tileset(tileset) {
+            println!("{}", layout.id);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::TilesetDiff { old, new }) = &args.command {
+        let old = tileset::Tileset::load_from_path(old, args.game)?;
+        let new = tileset::Tileset::load_from_path(new, args.game)?;
+        let diff = old.semantic_diff(&new)?;
+        if diff.is_empty() {
+            println!("tilesets are equivalent");
+        }
+        for (name, indices) in [
+            ("metatiles", &diff.metatiles),
+            ("tiles", &diff.tiles),
+            ("palettes", &diff.palettes),
+        ] {
+            if !indices.is_empty() {
+                println!(
+                    "{} {name} differ: {}",
+                    indices.len(),
+                    format_ranges(indices)
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::ContactSheet {
+        layouts: ids,
+        thumbnail_size,
+        columns,
+    }) = &args.command
+    {
+        let sheet = render_contact_sheet(&args, &layouts, ids, *thumbnail_size, *columns)?;
+        save_png(sheet, &output_file, args.png_compression)?;
+        return Ok(());
+    }
+
+    if let Some(Command::CheckAll { validate_only }) = &args.command {
+        return check_all(&args, &layouts, *validate_only);
+    }
+
+    if let Some(Command::Batch { manifest }) = &args.command {
+        return render_batch(&args, &layouts, manifest);
+    }
+
+    let Some(layout) = layouts.find(&map) else {
         log::error!("No layout matching name {map} found");
         std::process::exit(1);
     };
     log::info!("{:#?}", layout);
-    let primary = layout
-        .primary_tileset
-        .strip_prefix("gTileset_")
-        .unwrap()
-        .to_ascii_lowercase();
-    let secondary = tileset_dir(layout.secondary_tileset.strip_prefix("gTileset_").unwrap());
-    let primary_tileset_dir = format!("{PRET_ROOT}/data/tilesets/primary/{primary}");
-    let secondary_tileset_dir = format!("{PRET_ROOT}/data/tilesets/secondary/{secondary}");
-
-    let map_layout = map::Layout::load(
-        layout.width,
-        layout.height,
-        format!("{}/{}", env!("PRET_ROOT"), layout.blockdata_filepath),
-        format!("{}/{}", env!("PRET_ROOT"), layout.border_filepath),
+
+    if args.print_paths {
+        return print_paths(&args, layout);
+    }
+
+    let map_layout = layout.load(PRET_ROOT)?;
+
+    if let Some(Command::DumpCollision { scale }) = &args.command {
+        write_output(
+            &output_file,
+            &overlay::collision_mask_png(&map_layout, *scale)?,
+        )?;
+        return Ok(());
+    }
+
+    let mut tileset = load_tileset(&args, layout)?;
+
+    match args.command {
+        Some(Command::Stats) => {
+            print_stats(&tileset);
+            return Ok(());
+        }
+        Some(Command::DescribeMetatile { id }) => {
+            let Some(metatile) = tileset.metatile(id) else {
+                log::error!("No metatile with id {id} in either tileset");
+                std::process::exit(1);
+            };
+            describe_metatile(id, metatile);
+            return Ok(());
+        }
+        Some(Command::InspectMetatile { id, zoom }) => {
+            let image = tileset.get_metatile_image(id)?;
+            let indices = tileset.metatile_color_indices(id)?;
+            save_png(
+                overlay::pixel_grid(&image, &indices, zoom),
+                &output_file,
+                args.png_compression,
+            )?;
+            return Ok(());
+        }
+        Some(Command::Fill {
+            id,
+            size: (width, height),
+        }) => {
+            tileset.check_metatile(id)?;
+            // Checked before building the id list, which is as large as the image
+            render::image_size(width, height, 1)?;
+            let metatile_ids = vec![id; width as usize * height as usize];
+            save_png(
+                tileset.render_grid(&metatile_ids, width)?,
+                &output_file,
+                args.png_compression,
+            )?;
+            return Ok(());
+        }
+        Some(Command::Overview) => {
+            save_png(
+                render::render_overview(&map_layout, &tileset),
+                &output_file,
+                args.png_compression,
+            )?;
+            return Ok(());
+        }
+        Some(Command::DumpMetatile { id, zoom, sheet }) => {
+            let image = if sheet {
+                let Some(metatile) = tileset.metatile(id) else {
+                    log::error!("No metatile with id {id} in either tileset");
+                    std::process::exit(1);
+                };
+                let tiles = metatile
+                    .tiles()
+                    .iter()
+                    .map(|tile| (tile.clone(), tileset.tile_image(tile)))
+                    .collect::<Vec<_>>();
+                overlay::metatile_tile_sheet(&tiles, zoom)
+            } else {
+                let image = tileset.get_metatile_image(id)?;
+                let zoom = zoom.max(1);
+                image::imageops::resize(
+                    &image,
+                    image.width() * zoom,
+                    image.height() * zoom,
+                    image::imageops::FilterType::Nearest,
+                )
+            };
+            save_png(image, &output_file, args.png_compression)?;
+            return Ok(());
+        }
+        Some(Command::SelfDiff {
+            offset: (d_row, d_col),
+        }) => {
+            let differences = map_layout.shifted_differences(d_row, d_col);
+            print_status(
+                &output_file,
+                format_args!(
+                    "{} cells differ from the cell offset by ({d_row}, {d_col})",
+                    differences.len()
+                ),
+            );
+            let options = render::RenderOptions::default();
+            let mut image = render::render_layout(&map_layout, &tileset, &options)?.to_rgb8();
+            overlay::highlight_cells(
+                &mut image,
+                &differences,
+                render::Region::full(&map_layout),
+                1,
+                image::Rgb([255, 0, 0]),
+            );
+            save_png(image, &output_file, args.png_compression)?;
+            return Ok(());
+        }
+        Some(
+            Command::AffectedBy { .. }
+            | Command::Batch { .. }
+            | Command::CheckAll { .. }
+            | Command::ContactSheet { .. }
+            | Command::DumpCollision { .. }
+            | Command::TilesetDiff { .. },
+        )
+        | None => {}
+    }
+
+    if args.inspect {
+        print_inspect_report(&map_layout, &tileset);
+        return Ok(());
+    }
+
+    if args.prewarm {
+        if let Err(err) = tileset.prewarm() {
+            if args.strict {
+                return Err(err);
+            }
+            log::warn!("Couldn't prewarm the tilesets, rendering without atlases: {err}");
+        }
+    }
+
+    for (row, col, metatile_id) in tileset.validate_layout(&map_layout) {
+        log::warn!(
+            "Layout references metatile {metatile_id} at ({col}, {row}) but the tilesets only have {} primary and {} secondary metatiles",
+            tileset.primary().metatile_count(),
+            tileset.secondary().metatile_count()
+        );
+    }
+
+    let invalid_tiles = tileset.validate_tiles();
+    if let Some((metatile_id, tile_id)) = invalid_tiles.first() {
+        log::warn!(
+            "{} tile references are past the end of their tile sheet, starting with tile {tile_id} in metatile {metatile_id}. The primary sheet has {} tiles and the secondary {}",
+            invalid_tiles.len(),
+            tileset.primary().tile_count(),
+            tileset.secondary().tile_count()
+        );
+    }
+
+    let region = if args.autocrop {
+        let region = args
+            .region
+            .unwrap_or_else(|| render::Region::full(&map_layout))
+            .autocrop(&map_layout);
+        print_status(
+            &output_file,
+            format_args!(
+                "Cropped to {},{},{},{}",
+                region.x, region.y, region.width, region.height
+            ),
+        );
+        Some(region)
+    } else {
+        args.region
+    };
+
+    let mut options = render::RenderOptions::builder()
+        .scale(args.scale)
+        .region(region)
+        .grayscale(args.grayscale)
+        .background(args.background.unwrap_or_default())
+        .legend(args.legend)
+        .bottom_only(args.bottom_only)
+        .xray_bridges(args.xray_bridges)
+        .alpha_checker(args.alpha_checker)
+        .raw_indices(args.raw_indices)
+        .strict(args.strict);
+    for (from, to) in &args.remap {
+        options = options.remap(*from, *to);
+    }
+    if args.collision_labels {
+        options = options.overlay(render::Overlay::CollisionLabels);
+    }
+    if args.elevation {
+        options = options.overlay(render::Overlay::Elevation);
+    }
+    if args.wall_outlines {
+        options = options.overlay(render::Overlay::WallOutlines);
+    }
+    if args.tile_grid {
+        options = options.overlay(render::Overlay::TileGrid);
+    }
+
+    // Prints the --quiet summary of what was written, taking the time and unresolved count
+    // from the render itself
+    let summarize = |size: (u32, u32), pages: Option<usize>, elapsed: Duration, unresolved| {
+        if !args.quiet {
+            return;
+        }
+        let pages = pages
+            .map(|pages| format!(" pages={pages}"))
+            .unwrap_or_default();
+        print_status(
+            &output_file,
+            format_args!(
+                "layout={} size={}x{}{pages} output={} time_ms={} unresolved={unresolved}",
+                layout.id,
+                size.0,
+                size.1,
+                output_file.display(),
+                elapsed.as_millis()
+            ),
+        );
+    };
+
+    let started = Instant::now();
+
+    if let Some((rows, cols)) = args.tiles {
+        if output_file == Path::new(STDOUT) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--tiles writes a file per page and can't write to stdout",
+            ));
+        }
+        let pages = region
+            .unwrap_or_else(|| render::Region::full(&map_layout))
+            .split(rows, cols);
+        let page_count = pages.len();
+        // The largest page written, since pages on the last row and column may be smaller
+        let mut page_size = (0, 0);
+        let mut unresolved = 0;
+        for (page_row, page_col, page) in pages {
+            let page_options = options.clone().region(page).build();
+            let (page_image, page_unresolved) =
+                render::render_layout_with_unresolved(&map_layout, &tileset, &page_options)?;
+            page_size = (
+                page_size.0.max(page_image.width()),
+                page_size.1.max(page_image.height()),
+            );
+            unresolved += page_unresolved;
+            save_png(
+                page_image,
+                &page_path(&output_file, page_row, page_col),
+                args.png_compression,
+            )?;
+        }
+        summarize(page_size, Some(page_count), started.elapsed(), unresolved);
+        return Ok(());
+    }
+
+    let render_options = options.clone().build();
+    let (mut map_image, unresolved) =
+        render::render_layout_with_unresolved(&map_layout, &tileset, &render_options)?;
+    if !args.onion_skin.is_empty() {
+        let skinned = render_onion_skin(&args, &layouts, &map_image.to_rgb8(), &options)?;
+        map_image = image::DynamicImage::ImageRgb8(skinned);
+        if args.grayscale {
+            map_image = map_image.grayscale();
+        }
+    }
+    let size = (map_image.width(), map_image.height());
+    save_png(map_image, &output_file, args.png_compression)?;
+    let elapsed = started.elapsed();
+
+    if let Some(sidecar) = &args.sidecar {
+        let cells = sidecar_cells(&map_layout, &tileset, &render_options);
+        serde_json::to_writer(File::create(sidecar)?, &cells)?;
+    }
+
+    if let Some(report) = &args.report {
+        let cells = render::unresolved_cells(&map_layout, &tileset, &render_options)
+            .into_iter()
+            .map(|(row, col, metatile_id, err)| ReportCell {
+                row,
+                col,
+                metatile_id,
+                reason: err.to_string(),
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer(File::create(report)?, &cells)?;
+    }
+
+    summarize(size, None, elapsed, unresolved);
+    Ok(())
+}
+
+/// Writes `image` to `path`, or to stdout as base64 if `path` is [`STDOUT`].
+fn save_png(
+    image: impl Into<image::DynamicImage>,
+    path: &Path,
+    compression: render::PngCompression,
+) -> io::Result<()> {
+    if path == Path::new(STDOUT) {
+        let mut png_bytes = vec![];
+        render::write_png(&image.into(), &mut png_bytes, compression).map_err(io::Error::other)?;
+        return write_output(path, &png_bytes);
+    }
+    let file = io::BufWriter::new(File::create(path)?);
+    render::write_png(&image.into(), file, compression).map_err(io::Error::other)
+}
+
+/// Writes `bytes` to `path`, or to stdout as base64 if `path` is [`STDOUT`].
+fn write_output(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if path == Path::new(STDOUT) {
+        println!(
+            "{}",
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        );
+        Ok(())
+    } else {
+        std::fs::write(path, bytes)
+    }
+}
+
+/// Prints a status line, to stderr when stdout is carrying the image written to
+/// `output_file`.
+fn print_status(output_file: &Path, message: fmt::Arguments) {
+    if output_file == Path::new(STDOUT) {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+}
+
+/// Prints every path `layout` is loaded from, marking the ones which don't exist.
+fn print_paths(args: &Args, layout: &LayoutEntry) -> io::Result<()> {
+    let print = |label: &str, path: &Path| {
+        let missing = if path.exists() { "" } else { " (missing)" };
+        println!("{label}: {}{missing}", path.display());
+    };
+    print("layouts", Path::new(LAYOUTS_FILE));
+    print(
+        "blockdata",
+        &Path::new(PRET_ROOT).join(&layout.blockdata_filepath),
+    );
+    print(
+        "border",
+        &Path::new(PRET_ROOT).join(&layout.border_filepath),
+    );
+    let (primary, secondary) = tileset_dirs(args, layout)?;
+    print("primary tileset", &primary);
+    print("secondary tileset", &secondary);
+    Ok(())
+}
+
+/// The directories of `layout`'s primary and secondary tilesets, unless they're overridden
+/// on the command line.
+fn tileset_dirs(args: &Args, layout: &LayoutEntry) -> io::Result<(PathBuf, PathBuf)> {
+    let primary_tileset_dir = match &args.primary_tileset {
+        Some(dir) => dir.clone(),
+        None => tileset_dir("primary", &layout.primary_tileset)?.into(),
+    };
+    let secondary_tileset_dir = match &args.secondary_tileset {
+        Some(dir) => dir.clone(),
+        None => tileset_dir("secondary", &layout.secondary_tileset)?.into(),
+    };
+    Ok((primary_tileset_dir, secondary_tileset_dir))
+}
+
+/// Loads the tilesets of `layout`, unless they're overridden on the command line. With
+/// `--strict-palettes` every palette is parsed up front, failing on any which is malformed
+/// or entirely black.
+fn load_tileset(args: &Args, layout: &LayoutEntry) -> io::Result<tileset::LayoutTileset> {
+    let (primary_tileset_dir, secondary_tileset_dir) = tileset_dirs(args, layout)?;
+    let files = |palettes_dir: &Option<PathBuf>| -> io::Result<tileset::TilesetFiles> {
+        let mut files = tileset::TilesetFiles {
+            metatiles: args.metatiles_file.clone(),
+            attributes: args.attributes_file.clone(),
+            tiles: args.tiles_file.clone(),
+            ..Default::default()
+        };
+        // Made absolute so it isn't joined onto the tileset directory
+        if let Some(palettes_dir) = palettes_dir {
+            files.palettes = std::path::absolute(palettes_dir)?;
+        }
+        Ok(files)
+    };
+    let tileset = tileset::LayoutTileset::load_with_files(
+        primary_tileset_dir,
+        &files(&args.primary_palettes)?,
+        secondary_tileset_dir,
+        &files(&args.secondary_palettes)?,
+        args.game,
     )?;
+    if args.strict_palettes {
+        for (name, tileset) in [
+            ("primary", tileset.primary()),
+            ("secondary", tileset.secondary()),
+        ] {
+            tileset.prewarm_palettes()?;
+            let blank = tileset.blank_palettes();
+            if !blank.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{name} tileset has entirely black palettes in slots {blank:?}"),
+                ));
+            }
+        }
+    }
+    Ok(tileset
+        .with_primary_counts(
+            args.primary_metatiles
+                .unwrap_or(args.game.primary_metatile_count()),
+            args.primary_tiles.unwrap_or(args.game.primary_tile_count()),
+        )
+        .with_primary_palette_count(
+            args.primary_palette_count
+                .unwrap_or(args.game.primary_palette_count()),
+        )
+        .with_forced_palette(args.force_palette))
+}
 
-    let tileset =
-        tileset::LayoutTileset::load_from_paths(primary_tileset_dir, secondary_tileset_dir)?;
+/// Draws the layouts named by `--onion-skin` faded around `map_image`, each rendered with
+/// the same options as the map apart from the region.
+fn render_onion_skin(
+    args: &Args,
+    layouts: &Layouts,
+    map_image: &image::RgbImage,
+    options: &render::RenderOptionsBuilder,
+) -> io::Result<image::RgbImage> {
+    let options = options.clone().region(None::<render::Region>).build();
+    let mut neighbors = vec![];
+    for (direction, id, offset) in &args.onion_skin {
+        let Some(entry) = layouts.get(id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no layout matching name {id} found"),
+            ));
+        };
+        let layout = entry.load(PRET_ROOT)?;
+        let tileset = load_tileset(args, entry)?;
+        neighbors.push(render::Neighbor {
+            direction: *direction,
+            offset: *offset,
+            image: render::render_layout(&layout, &tileset, &options)?.to_rgb8(),
+        });
+    }
+    Ok(render::onion_skin(
+        map_image,
+        &neighbors,
+        args.onion_margin,
+        options.scale(),
+        args.onion_opacity,
+        args.background.unwrap_or_default(),
+    ))
+}
 
-    let mut map_image: RgbImage = ImageBuffer::new(
-        METATILE_DIMENSION * layout.width,
-        METATILE_DIMENSION * layout.height,
+/// Renders a thumbnail of each layout in `ids`, or of every layout if it's empty, and lays
+/// them out in a grid. Layouts which fail to load or render are skipped with a warning.
+fn render_contact_sheet(
+    args: &Args,
+    layouts: &Layouts,
+    ids: &[String],
+    thumbnail_size: u32,
+    columns: Option<u32>,
+) -> io::Result<image::RgbImage> {
+    let entries = if ids.is_empty() {
+        layouts.iter().collect::<Vec<_>>()
+    } else {
+        ids.iter()
+            .map(|id| {
+                layouts.get(id).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("no layout named {id}"))
+                })
+            })
+            .collect::<io::Result<_>>()?
+    };
+
+    let options = render::RenderOptions::default();
+    let mut thumbnails = vec![];
+    for entry in entries {
+        let rendered = entry.load(PRET_ROOT).and_then(|layout| {
+            let tileset = load_tileset(args, entry)?;
+            render::render_layout(&layout, &tileset, &options)
+        });
+        let image = match rendered {
+            Ok(image) => image.to_rgb8(),
+            Err(err) => {
+                log::warn!("Skipping layout {}: {err}", entry.id);
+                continue;
+            }
+        };
+        thumbnails.push((entry.id.clone(), render::thumbnail(&image, thumbnail_size)));
+    }
+
+    let columns = columns.unwrap_or_else(|| (thumbnails.len() as f64).sqrt().ceil() as u32);
+    Ok(render::contact_sheet(
+        &thumbnails,
+        columns,
+        args.background.unwrap_or_default(),
+    ))
+}
+
+fn check_all(args: &Args, layouts: &Layouts, validate_only: bool) -> io::Result<()> {
+    let mut failed = 0;
+    let mut total = 0;
+    for layout in layouts.iter() {
+        total += 1;
+        match check_layout(args, layout, validate_only) {
+            Ok(()) => println!("PASS {}", layout.id),
+            Err(err) => {
+                failed += 1;
+                println!("FAIL {}: {err}", layout.id);
+            }
+        }
+    }
+
+    println!("{} of {total} layouts passed", total - failed);
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{failed} of {total} layouts failed"
+        )))
+    }
+}
+
+fn check_layout(args: &Args, layout: &LayoutEntry, validate_only: bool) -> io::Result<()> {
+    let map_layout = layout.load(PRET_ROOT)?;
+    let tileset = load_tileset(args, layout)?;
+    if let Some((row, col, metatile_id)) = tileset.validate_layout(&map_layout).first() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no metatile with id {metatile_id} at ({col}, {row})"),
+        ));
+    }
+    // Only metatiles the layout uses count, a shared tileset's broken metatiles shouldn't
+    // fail every layout drawing from it
+    let metatile_ids = map_layout
+        .metatiles()
+        .iter()
+        .map(|metatile| metatile.metatile_id())
+        .collect::<HashSet<_>>();
+    if let Some((metatile_id, tile_id)) = tileset
+        .validate_tiles()
+        .into_iter()
+        .find(|(metatile_id, _)| metatile_ids.contains(metatile_id))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("metatile {metatile_id} uses tile {tile_id}, past the end of its tile sheet"),
+        ));
+    }
+
+    let options = render::RenderOptions::builder().strict(true).build();
+    if validate_only {
+        for metatile_id in metatile_ids {
+            tileset.check_metatile(metatile_id)?;
+        }
+    } else {
+        render::render_layout(&map_layout, &tileset, &options)?;
+    }
+    Ok(())
+}
+
+fn render_batch(args: &Args, layouts: &Layouts, manifest_file: &Path) -> io::Result<()> {
+    let manifest: BatchManifest =
+        serde_json::from_reader(File::open(manifest_file)?).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {err}", manifest_file.display()),
+            )
+        })?;
+    let base_dir = manifest_file.parent().unwrap_or(Path::new(""));
+
+    let mut failures = vec![];
+    for entry in &manifest.renders {
+        if let Err(err) = render_batch_entry(args, layouts, base_dir, entry) {
+            log::error!("Failed to render {}: {err}", entry.layout);
+            failures.push(entry.layout.as_str());
+        }
+    }
+
+    println!(
+        "rendered {} of {} layouts",
+        manifest.renders.len() - failures.len(),
+        manifest.renders.len()
     );
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "failed to render {}",
+            failures.join(", ")
+        )))
+    }
+}
+
+fn render_batch_entry(
+    args: &Args,
+    layouts: &Layouts,
+    base_dir: &Path,
+    entry: &BatchRender,
+) -> io::Result<()> {
+    let layout = layouts
+        .get(&entry.layout)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no layout with that id"))?;
+    let region = entry
+        .region
+        .as_deref()
+        .map(str::parse::<render::Region>)
+        .transpose()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut options = render::RenderOptions::builder()
+        .scale(entry.scale.unwrap_or(1))
+        .region(region)
+        .grayscale(entry.grayscale)
+        .bottom_only(entry.bottom_only);
+    for overlay in &entry.overlays {
+        options = options.overlay(*overlay);
+    }
+    let options = options.build();
 
-    for row in 0..layout.height {
-        for col in 0..layout.width {
-            let metatile_data = map_layout.get_metatile(row, col).unwrap();
-            let metatile_left_pixel = col * METATILE_DIMENSION;
-            let metatile_top_pixel = row * METATILE_DIMENSION;
-            log::debug!("Metatile id: {}", metatile_data.metatile_id);
-            if let Some(metatile_image) = tileset.get_metatile_image(metatile_data.metatile_id) {
-                map_image
-                    .sub_image(
-                        metatile_left_pixel,
-                        metatile_top_pixel,
-                        METATILE_DIMENSION,
-                        METATILE_DIMENSION,
-                    )
-                    .copy_from(&metatile_image, 0, 0)
-                    .expect("Should be able to copy into subimage");
+    let map_layout = layout.load(PRET_ROOT)?;
+    let tileset = load_tileset(args, layout)?;
+    render::output_size(&map_layout, &options)?;
+    let image = render::render_layout(&map_layout, &tileset, &options)?;
+    let output_file = base_dir.join(&entry.output);
+    save_png(image, &output_file, args.png_compression)?;
+    log::info!("Rendered {} to {}", entry.layout, output_file.display());
+    Ok(())
+}
+
+fn print_stats(layout_tileset: &tileset::LayoutTileset) {
+    let primary_tile_count = layout_tileset.primary_tile_count();
+    for (name, role, tileset) in [
+        (
+            "primary",
+            tileset::TilesetRole::Primary,
+            layout_tileset.primary(),
+        ),
+        (
+            "secondary",
+            tileset::TilesetRole::Secondary,
+            layout_tileset.secondary(),
+        ),
+    ] {
+        let unused_tiles = tileset.unused_tiles(role, primary_tile_count);
+        println!("{name} tileset:");
+        println!("  metatiles: {}", tileset.metatile_count());
+        println!("  palettes: {}", tileset.palette_count());
+        println!("  unused palettes: {:?}", tileset.unused_palettes());
+        println!(
+            "  unused tiles: {} of {}",
+            unused_tiles.len(),
+            tileset.tile_count()
+        );
+        if !unused_tiles.is_empty() {
+            println!("    {}", format_ranges(&unused_tiles));
+        }
+    }
+}
+
+/// Writes sorted indices compactly, with consecutive runs as ranges, e.g. `1-3, 7, 9-10`.
+fn format_ranges(indices: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &index in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == index => *end = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
             } else {
-                log::error!("Failed to get metatile image at coordinate: ({col}, {row})");
+                format!("{start}-{end}")
             }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_inspect_report(layout: &map::Layout, tileset: &tileset::LayoutTileset) {
+    println!("layout:");
+    println!("  size: {}x{}", layout.width(), layout.height());
+    println!(
+        "  metatiles: {}",
+        layout.width() as usize * layout.height() as usize
+    );
+    println!(
+        "  border size: {}x{}",
+        layout.border_width(),
+        layout.border_height()
+    );
+    for (name, tileset) in [
+        ("primary", tileset.primary()),
+        ("secondary", tileset.secondary()),
+    ] {
+        let (sheet_width, sheet_height) = tileset.tile_sheet_size();
+        println!("{name} tileset:");
+        println!("  metatiles: {}", tileset.metatile_count());
+        println!("  tiles: {}", tileset.tile_count());
+        if let Some(max_tile_id) = tileset.max_referenced_tile_id() {
+            println!("  highest tile id used: {max_tile_id}");
         }
+        println!("  tile sheet: {sheet_width}x{sheet_height}");
+        println!("  palettes: {}", tileset.palette_count());
+    }
+    println!(
+        "bottom layer tiles mostly index 0: {}",
+        tileset.index_zero_usage().len()
+    );
+    let invalid = tileset.validate_layout(layout);
+    println!("out of range metatiles: {}", invalid.len());
+    for (row, col, metatile_id) in invalid {
+        println!("  ({col}, {row}): {metatile_id}");
     }
+}
+
+fn sidecar_cells(
+    layout: &map::Layout,
+    tileset: &tileset::LayoutTileset,
+    options: &render::RenderOptions,
+) -> Vec<SidecarCell> {
+    let region = options.rendered_region(layout);
+    let cell_size = METATILE_DIMENSION * options.scale();
+    let mut cells = vec![];
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let Some(metatile_data) = layout.get_metatile(row, col) else {
+                continue;
+            };
+            let (x, y) = dimensions::metatile_origin(row - region.y, col - region.x);
+            cells.push(SidecarCell {
+                row,
+                col,
+                x: x * options.scale(),
+                y: y * options.scale(),
+                width: cell_size,
+                height: cell_size,
+                metatile_id: metatile_data.metatile_id(),
+                collision: metatile_data.collision(),
+                elevation: metatile_data.elevation(),
+                behavior: tileset
+                    .metatile(metatile_data.metatile_id())
+                    .map(|metatile| metatile.attributes().behavior()),
+            });
+        }
+    }
+    cells
+}
 
-    map_image.save(output_file).unwrap();
+fn describe_metatile(id: u16, metatile: &tileset::Metatile) {
+    let attributes = metatile.attributes();
+    println!("metatile {id}:");
+    println!("  layer type: {}", attributes.layer_type());
+    println!("  behavior: {:#04x}", attributes.behavior());
+    println!("  attributes: {:#010x}", attributes.raw());
+    println!("  layer  pos  tile  palette  hflip  vflip");
+    for (index, tile) in metatile.tiles().iter().enumerate() {
+        let layer = if index < dimensions::TILES_PER_LAYER {
+            "bottom"
+        } else {
+            "top"
+        };
+        println!(
+            "  {layer:<6} {:>3}  {:>4}  {:>7}  {:>5}  {:>5}",
+            index % dimensions::TILES_PER_LAYER,
+            tile.tile_id(),
+            tile.palette_number(),
+            tile.flip_horizontal(),
+            tile.flip_vertical()
+        );
+    }
+}
 
-    Ok(())
+/// The path of one page of a split render, e.g. `out.png` becomes `out_0_1.png`.
+fn page_path(output_file: &Path, row: u32, col: u32) -> PathBuf {
+    let stem = output_file
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut file_name = format!("{stem}_{row}_{col}");
+    if let Some(extension) = output_file.extension() {
+        file_name.push('.');
+        file_name.push_str(&extension.to_string_lossy());
+    }
+    output_file.with_file_name(file_name)
+}
+
+fn parse_pages(s: &str) -> Result<(u32, u32), String> {
+    let Some((rows, cols)) = s.split_once(',') else {
+        return Err(format!("invalid page grid {s}, expected rows,cols"));
+    };
+    let parse = |value: &str| match value.trim().parse::<u32>() {
+        Ok(0) => Err(format!(
+            "invalid page grid {s}, needs at least one row and column"
+        )),
+        Ok(value) => Ok(value),
+        Err(err) => Err(format!("invalid page grid {s}: {err}")),
+    };
+    Ok((parse(rows)?, parse(cols)?))
+}
+
+fn parse_remap(s: &str) -> Result<(u16, u16), String> {
+    let Some((from, to)) = s.split_once('=') else {
+        return Err(format!("invalid remap {s}, expected FROM=TO"));
+    };
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<u16>()
+            .map_err(|err| format!("invalid remap {s}: {err}"))
+    };
+    Ok((parse(from)?, parse(to)?))
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let Some((width, height)) = s.split_once('x') else {
+        return Err(format!("invalid size {s}, expected WIDTHxHEIGHT"));
+    };
+    let parse = |value: &str| match value.trim().parse::<u32>() {
+        Ok(0) => Err(format!(
+            "invalid size {s}, needs a width and height of at least 1"
+        )),
+        Ok(value) => Ok(value),
+        Err(err) => Err(format!("invalid size {s}: {err}")),
+    };
+    Ok((parse(width)?, parse(height)?))
 }
 
-fn tileset_dir(tileset_name: &str) -> String {
-    tileset_name.to_case(convert_case::Case::Snake)
+fn parse_neighbor(s: &str) -> Result<(render::Direction, String, i32), String> {
+    let Some((direction, layout)) = s.split_once('=') else {
+        return Err(format!(
+            "invalid neighbor {s}, expected DIRECTION=LAYOUT[:OFFSET]"
+        ));
+    };
+    let (layout, offset) = match layout.split_once(':') {
+        Some((layout, offset)) => (
+            layout,
+            offset
+                .trim()
+                .parse::<i32>()
+                .map_err(|err| format!("invalid neighbor offset {offset}: {err}"))?,
+        ),
+        None => (layout, 0),
+    };
+    Ok((direction.trim().parse()?, layout.trim().to_string(), offset))
+}
+
+fn parse_offset(s: &str) -> Result<(i64, i64), String> {
+    let Some((rows, cols)) = s.split_once(',') else {
+        return Err(format!("invalid offset {s}, expected rows,cols"));
+    };
+    let parse = |value: &str| {
+        value
+            .trim()
+            .parse::<i64>()
+            .map_err(|err| format!("invalid offset {s}: {err}"))
+    };
+    Ok((parse(rows)?, parse(cols)?))
+}
+
+/// Resolves a tileset symbol such as `gTileset_PowerPlant` to its directory in the pret
+/// checkout, e.g. `data/tilesets/secondary/power_plant`. `kind` is either `primary` or
+/// `secondary`.
+fn tileset_dir(kind: &str, tileset_name: &str) -> io::Result<String> {
+    let Some(name) = tileset_name.strip_prefix("gTileset_") else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{kind} tileset {tileset_name} doesn't start with gTileset_"),
+        ));
+    };
+    let dir_name = name.to_case(convert_case::Case::Snake);
+    Ok(format!("{PRET_ROOT}/data/tilesets/{kind}/{dir_name}"))
 }
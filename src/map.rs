@@ -1,35 +1,158 @@
+//! A layout's blockdata and border: the metatile id, collision and elevation of each cell.
+//!
+//! Nothing here needs a tileset, so tools which only analyze map data can load a
+//! [`Layout`] on its own and use accessors such as [`Layout::metatile_histogram`] and
+//! [`Layout::collision_grid`], or serialize it to JSON, without decoding any tile sheets or
+//! palettes.
+
+#[cfg(feature = "fs")]
+use crate::gzip;
 use byteorder::{LittleEndian, ReadBytesExt};
-use std::{
-    io::{self, Read},
-    path::Path,
-};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
 
+#[derive(Serialize)]
 pub struct Layout {
     height: u32,
     width: u32,
+    border_height: u32,
+    border_width: u32,
     map_data: MapData,
 }
 
 impl Layout {
+    /// Loads a layout, inferring the border dimensions from the length of the border file.
+    /// Borders are assumed to be square, which holds for every FRLG and RSE layout.
+    #[cfg(feature = "fs")]
     pub fn load(
         width: u32,
         height: u32,
         map_path: impl AsRef<Path>,
         border_path: impl AsRef<Path>,
     ) -> io::Result<Self> {
+        Self::from_map_data(width, height, MapData::from_files(map_path, border_path)?)
+    }
+
+    /// Loads a layout whose border is `border_width` by `border_height` metatiles.
+    #[cfg(feature = "fs")]
+    pub fn load_with_border_size(
+        width: u32,
+        height: u32,
+        border_width: u32,
+        border_height: u32,
+        map_path: impl AsRef<Path>,
+        border_path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let map_data = MapData::from_files(map_path, border_path)?;
+        Self::from_map_data_with_border_size(width, height, border_width, border_height, map_data)
+    }
+
+    /// Builds a layout from the raw contents of its blockdata and border files, inferring
+    /// the border dimensions like [`Layout::load`].
+    pub fn from_bytes(
+        width: u32,
+        height: u32,
+        map_data: &[u8],
+        border_data: &[u8],
+    ) -> io::Result<Self> {
+        Self::from_map_data(width, height, MapData::from_bytes(map_data, border_data)?)
+    }
+
+    /// Builds a layout from the raw contents of its blockdata and border files.
+    pub fn from_bytes_with_border_size(
+        width: u32,
+        height: u32,
+        border_width: u32,
+        border_height: u32,
+        map_data: &[u8],
+        border_data: &[u8],
+    ) -> io::Result<Self> {
+        let map_data = MapData::from_bytes(map_data, border_data)?;
+        Self::from_map_data_with_border_size(width, height, border_width, border_height, map_data)
+    }
+
+    fn from_map_data(width: u32, height: u32, map_data: MapData) -> io::Result<Self> {
+        let border_len = map_data.borders.len() as u32;
+        let border_dimension = border_len.isqrt();
+        if border_dimension * border_dimension != border_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("cannot infer the dimensions of a border of {border_len} metatiles"),
+            ));
+        }
+        Self::from_map_data_with_border_size(
+            width,
+            height,
+            border_dimension,
+            border_dimension,
+            map_data,
+        )
+    }
+
+    fn from_map_data_with_border_size(
+        width: u32,
+        height: u32,
+        border_width: u32,
+        border_height: u32,
+        map_data: MapData,
+    ) -> io::Result<Self> {
+        let map_len = checked_area(width, height).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("layout of {width}x{height} metatiles is too large"),
+            )
+        })?;
+        if map_data.metatiles.len() != map_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "map has {} metatiles, expected {width}x{height}",
+                    map_data.metatiles.len()
+                ),
+            ));
+        }
+        if Some(map_data.borders.len()) != checked_area(border_width, border_height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "border has {} metatiles, expected {border_width}x{border_height}",
+                    map_data.borders.len()
+                ),
+            ));
+        }
         Ok(Self {
             width,
             height,
-            map_data: MapData::from_files(map_path, border_path)?,
+            border_width,
+            border_height,
+            map_data,
         })
     }
 
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn border_width(&self) -> u32 {
+        self.border_width
+    }
+
+    pub fn border_height(&self) -> u32 {
+        self.border_height
+    }
+
     fn tile_idx(&self, row: u32, col: u32) -> Option<usize> {
         if row >= self.height || col >= self.width {
             None
         } else {
-            let idx = row * self.width + col;
-            Some(idx as usize)
+            Some(row as usize * self.width as usize + col as usize)
         }
     }
 
@@ -42,38 +165,223 @@ impl Layout {
         self.tile_idx(row, col)
             .map(|idx| &mut self.map_data.metatiles[idx])
     }
+
+    /// The collision value of the cell at `(row, col)`, or `None` outside the map.
+    pub fn get_collision(&self, row: u32, col: u32) -> Option<u8> {
+        self.get_metatile(row, col)
+            .map(|metatile| metatile.collision())
+    }
+
+    /// The elevation of the cell at `(row, col)`, or `None` outside the map.
+    pub fn get_elevation(&self, row: u32, col: u32) -> Option<u8> {
+        self.get_metatile(row, col)
+            .map(|metatile| metatile.elevation())
+    }
+
+    /// Every cell of the map in row-major order, with [`Layout::width`] cells per row.
+    pub fn metatiles(&self) -> &[MapMetatileData] {
+        &self.map_data.metatiles
+    }
+
+    /// Whether each cell of the map can be walked on, in row-major order with
+    /// [`Layout::width`] cells per row. A cell is passable when its collision value is 0.
+    pub fn collision_grid(&self) -> Vec<bool> {
+        self.map_data
+            .metatiles
+            .iter()
+            .map(MapMetatileData::is_passable)
+            .collect()
+    }
+
+    /// How many cells of the map use each metatile id.
+    pub fn metatile_histogram(&self) -> HashMap<u16, usize> {
+        let mut histogram = HashMap::new();
+        for metatile in &self.map_data.metatiles {
+            *histogram.entry(metatile.metatile_id()).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// The most common metatile id in the map and how many cells use it, typically the
+    /// map's background fill. Ties go to the lowest id, and an empty map has none.
+    pub fn dominant_metatile(&self) -> Option<(u16, usize)> {
+        self.metatile_histogram()
+            .into_iter()
+            .max_by_key(|(metatile_id, count)| (*count, std::cmp::Reverse(*metatile_id)))
+    }
+
+    /// Compares the map against a copy of itself shifted by `(d_row, d_col)` cells, returning
+    /// the `(row, col)` of every cell whose metatile differs from the one in the cell that far
+    /// away. Collision and elevation are ignored. Cells whose counterpart falls outside the
+    /// map are skipped.
+    pub fn shifted_differences(&self, d_row: i64, d_col: i64) -> Vec<(u32, u32)> {
+        let mut differences = vec![];
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let (Ok(other_row), Ok(other_col)) = (
+                    u32::try_from(i64::from(row) + d_row),
+                    u32::try_from(i64::from(col) + d_col),
+                ) else {
+                    continue;
+                };
+                let Some(other) = self.get_metatile(other_row, other_col) else {
+                    continue;
+                };
+                let metatile_id = self.get_metatile(row, col).map(|cell| cell.metatile_id());
+                if metatile_id != Some(other.metatile_id()) {
+                    differences.push((row, col));
+                }
+            }
+        }
+        differences
+    }
+
+    pub fn get_border_metatile(&self, row: u32, col: u32) -> Option<MapMetatileData> {
+        self.border().get(row, col)
+    }
+
+    /// The border block the game repeats around the map, as its own grid.
+    pub fn border(&self) -> BorderGrid<'_> {
+        BorderGrid {
+            width: self.border_width,
+            height: self.border_height,
+            cells: &self.map_data.borders,
+        }
+    }
+}
+
+/// A layout's border block, 2x2 metatiles in FRLG unless the layout says otherwise. Cells use
+/// the same metatile ids as the map.
+#[derive(Debug, Clone, Copy)]
+pub struct BorderGrid<'a> {
+    width: u32,
+    height: u32,
+    cells: &'a [MapMetatileData],
 }
 
+impl<'a> BorderGrid<'a> {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The cell at `(row, col)` of the border, or `None` outside it.
+    pub fn get(&self, row: u32, col: u32) -> Option<MapMetatileData> {
+        if row >= self.height || col >= self.width {
+            None
+        } else {
+            Some(self.cells[row as usize * self.width as usize + col as usize])
+        }
+    }
+
+    /// Every cell of the border as `(row, col, cell)`, in row-major order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32, MapMetatileData)> + 'a {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(idx, cell)| (idx as u32 / width, idx as u32 % width, *cell))
+    }
+}
+
+/// The number of cells in a `width` by `height` grid, or `None` if it doesn't fit in a usize.
+fn checked_area(width: u32, height: u32) -> Option<usize> {
+    (width as usize).checked_mul(height as usize)
+}
+
+#[derive(Serialize)]
 pub struct MapData {
     pub metatiles: Vec<MapMetatileData>,
-    _borders: Vec<MapMetatileData>,
+    pub borders: Vec<MapMetatileData>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// One cell of a layout. Deserializing checks every field fits in its bitfield, as with
+/// [`MapMetatileData::new`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "MapMetatileFields")]
 pub struct MapMetatileData {
-    pub metatile_id: u16,
-    _collision_data: u8,
-    _elevation: u8,
+    metatile_id: MetatileId,
+    collision: u8,
+    elevation: u8,
+}
+
+/// The unchecked fields of a [`MapMetatileData`] as they appear in JSON.
+#[derive(Deserialize)]
+struct MapMetatileFields {
+    metatile_id: u16,
+    collision: u8,
+    elevation: u8,
+}
+
+impl TryFrom<MapMetatileFields> for MapMetatileData {
+    type Error = io::Error;
+
+    fn try_from(fields: MapMetatileFields) -> io::Result<Self> {
+        Self::new(fields.metatile_id, fields.collision, fields.elevation)
+    }
+}
+
+/// A metatile id which fits in the 10 bits a map cell has for it, so it can be written
+/// back out without spilling into the collision and elevation bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "u16", try_from = "u16")]
+pub struct MetatileId(u16);
+
+impl MetatileId {
+    pub fn new(metatile_id: u16) -> io::Result<Self> {
+        check_field("metatile id", metatile_id, MAX_METATILE_ID)?;
+        Ok(MetatileId(metatile_id))
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl TryFrom<u16> for MetatileId {
+    type Error = io::Error;
+
+    fn try_from(metatile_id: u16) -> io::Result<Self> {
+        Self::new(metatile_id)
+    }
+}
+
+impl From<MetatileId> for u16 {
+    fn from(metatile_id: MetatileId) -> Self {
+        metatile_id.0
+    }
+}
+
+impl std::fmt::Display for MetatileId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 impl MapData {
+    #[cfg(feature = "fs")]
     pub fn from_files(
         map_path: impl AsRef<Path>,
         border_path: impl AsRef<Path>,
     ) -> std::io::Result<Self> {
-        let mut map_bin = std::fs::File::open(map_path)?;
-        let mut border_bin = std::fs::File::open(border_path)?;
-        let mut map_data = vec![];
-        let mut border_data = vec![];
+        let map_data = gzip::read(&map_path)?;
+        let border_data = gzip::read(&border_path)?;
 
-        map_bin.read_to_end(&mut map_data)?;
-        border_bin.read_to_end(&mut border_data)?;
+        let map_name = format!("blockdata file {}", map_path.as_ref().display());
+        let border_name = format!("border file {}", border_path.as_ref().display());
+        check_even_length(&map_name, &map_data)?;
+        check_even_length(&border_name, &border_data)?;
+        Self::from_bytes(&map_data, &border_data)
+    }
 
-        if map_data.len() % 2 == 1 || border_data.len() % 2 == 1 {
-            return Err(std::io::ErrorKind::InvalidData.into());
-        }
+    pub fn from_bytes(map_data: &[u8], border_data: &[u8]) -> std::io::Result<Self> {
+        check_even_length("blockdata", map_data)?;
+        check_even_length("border data", border_data)?;
 
-        let mut map_data_cursor = std::io::Cursor::new(&map_data);
+        let mut map_data_cursor = std::io::Cursor::new(map_data);
         let metatile_data = (0..map_data.len())
             .step_by(2)
             .map_while(|_| match map_data_cursor.read_u16::<LittleEndian>() {
@@ -81,7 +389,7 @@ impl MapData {
                 Err(_) => None,
             })
             .collect();
-        let mut border_data_cursor = std::io::Cursor::new(&border_data);
+        let mut border_data_cursor = std::io::Cursor::new(border_data);
         let border_data = (0..border_data.len())
             .step_by(2)
             .map_while(|_| match border_data_cursor.read_u16::<LittleEndian>() {
@@ -92,17 +400,101 @@ impl MapData {
 
         Ok(MapData {
             metatiles: metatile_data,
-            _borders: border_data,
+            borders: border_data,
+        })
+    }
+}
+
+/// Map cells are stored as little endian u16s, so a valid file has an even length.
+fn check_even_length(name: &str, data: &[u8]) -> io::Result<()> {
+    if data.len().is_multiple_of(2) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{name} has odd length {}, expected a multiple of 2",
+                data.len()
+            ),
+        ))
+    }
+}
+
+const MAX_METATILE_ID: u16 = 0x3ff;
+const MAX_COLLISION: u8 = 0x3;
+const MAX_ELEVATION: u8 = 0xf;
+
+fn check_field<T: PartialOrd + std::fmt::Display>(name: &str, value: T, max: T) -> io::Result<()> {
+    if value > max {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name} {value} doesn't fit in its bitfield, max is {max}"),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+impl MapMetatileData {
+    /// Creates a cell, checking that each value fits in its bitfield: 10 bits for the
+    /// metatile id, 2 for collision and 4 for elevation.
+    pub fn new(metatile_id: u16, collision: u8, elevation: u8) -> io::Result<Self> {
+        let metatile_id = MetatileId::new(metatile_id)?;
+        check_field("collision", collision, MAX_COLLISION)?;
+        check_field("elevation", elevation, MAX_ELEVATION)?;
+        Ok(MapMetatileData {
+            metatile_id,
+            collision,
+            elevation,
         })
     }
+
+    pub fn with_metatile_id(self, metatile_id: u16) -> io::Result<Self> {
+        Self::new(metatile_id, self.collision, self.elevation)
+    }
+
+    pub fn with_collision(self, collision: u8) -> io::Result<Self> {
+        Self::new(self.metatile_id(), collision, self.elevation)
+    }
+
+    pub fn with_elevation(self, elevation: u8) -> io::Result<Self> {
+        Self::new(self.metatile_id(), self.collision, elevation)
+    }
+
+    pub fn metatile_id(&self) -> u16 {
+        self.metatile_id.get()
+    }
+
+    pub fn set_metatile_id(&mut self, metatile_id: MetatileId) {
+        self.metatile_id = metatile_id;
+    }
+
+    pub fn collision(&self) -> u8 {
+        self.collision
+    }
+
+    pub fn elevation(&self) -> u8 {
+        self.elevation
+    }
+
+    /// The cell packed as it's stored in blockdata. Every bit is decoded, so for a cell
+    /// read from a file this is exactly the value that was read.
+    pub fn raw(&self) -> u16 {
+        self.metatile_id.get() | u16::from(self.collision) << 10 | u16::from(self.elevation) << 12
+    }
+
+    /// Whether the cell's collision value allows walking onto it.
+    pub fn is_passable(&self) -> bool {
+        self.collision == 0
+    }
 }
 
 impl From<u16> for MapMetatileData {
     fn from(value: u16) -> Self {
         MapMetatileData {
-            metatile_id: value & 0x03ff,
-            _collision_data: ((value & 0x0c00) >> 10) as u8,
-            _elevation: ((value & 0xf000) >> 12) as u8,
+            metatile_id: MetatileId(value & MAX_METATILE_ID),
+            collision: ((value & 0x0c00) >> 10) as u8,
+            elevation: ((value & 0xf000) >> 12) as u8,
         }
     }
 }
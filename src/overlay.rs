@@ -0,0 +1,347 @@
+use crate::dimensions::{
+    metatile_origin, METATILE_DIMENSION, METATILE_TILE_GRID, TILES_PER_LAYER, TILE_DIMENSION,
+};
+use crate::font::{draw_text, fill_rect, text_height, text_width};
+use crate::map::Layout;
+use crate::render::{Overlay, Region};
+use crate::tileset::TileData;
+use image::{imageops::FilterType, GrayImage, ImageBuffer, Rgb, RgbImage, RgbaImage};
+use std::io;
+
+/// Size of one font pixel, in map pixels, when labelling a metatile.
+const LABEL_PIXEL_SIZE: u32 = 2;
+const LABEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+const LABEL_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// Tint applied to cells by [`Overlay::Elevation`], indexed by elevation.
+pub const ELEVATION_COLORS: [Rgb<u8>; 16] = [
+    Rgb([0, 0, 0]),
+    Rgb([230, 25, 75]),
+    Rgb([60, 180, 75]),
+    Rgb([255, 225, 25]),
+    Rgb([0, 130, 200]),
+    Rgb([245, 130, 48]),
+    Rgb([145, 30, 180]),
+    Rgb([70, 240, 240]),
+    Rgb([240, 50, 230]),
+    Rgb([210, 245, 60]),
+    Rgb([250, 190, 212]),
+    Rgb([0, 128, 128]),
+    Rgb([220, 190, 255]),
+    Rgb([170, 110, 40]),
+    Rgb([128, 0, 0]),
+    Rgb([255, 255, 255]),
+];
+
+/// Colors of a collision mask, indexed by collision value. Only 0 is passable.
+pub const COLLISION_COLORS: [Rgb<u8>; 4] = [
+    Rgb([0, 160, 0]),
+    Rgb([200, 0, 0]),
+    Rgb([0, 0, 200]),
+    Rgb([200, 200, 0]),
+];
+
+const GRID_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+const TILE_GRID_COLOR: Rgb<u8> = Rgb([128, 128, 128]);
+const WALL_OUTLINE_COLOR: Rgb<u8> = Rgb([255, 0, 0]);
+
+/// Size of one font pixel and edge of each swatch in a legend, before scaling.
+const LEGEND_PIXEL_SIZE: u32 = 2;
+const LEGEND_SWATCH: u32 = 10;
+const LEGEND_PADDING: u32 = 4;
+
+/// Writes the collision value of every impassable cell as a number centered in the cell.
+/// `image` is a render of `region` of the layout, with `scale` output pixels per map pixel.
+pub fn draw_collision_labels(image: &mut RgbImage, layout: &Layout, region: Region, scale: u32) {
+    let pixel_size = LABEL_PIXEL_SIZE * scale;
+    let cell_size = METATILE_DIMENSION * scale;
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let Some(metatile) = layout.get_metatile(row, col) else {
+                continue;
+            };
+            if metatile.collision() == 0 {
+                continue;
+            }
+
+            let label = metatile.collision().to_string();
+            let width = text_width(&label, pixel_size);
+            let height = text_height(pixel_size);
+            let (cell_x, cell_y) = metatile_origin(row - region.y, col - region.x);
+            let x = cell_x * scale + (cell_size - width) / 2;
+            let y = cell_y * scale + (cell_size - height) / 2;
+            fill_rect(
+                image,
+                x - scale,
+                y - scale,
+                width + 2 * scale,
+                height + 2 * scale,
+                LABEL_BACKGROUND,
+            );
+            draw_text(image, &label, x, y, pixel_size, LABEL_COLOR);
+        }
+    }
+}
+
+/// Outlines every impassable cell, one map pixel thick, leaving passable cells untouched.
+/// `image` is a render of `region` of the layout, with `scale` output pixels per map pixel.
+pub fn draw_wall_outlines(image: &mut RgbImage, layout: &Layout, region: Region, scale: u32) {
+    let cell_size = METATILE_DIMENSION * scale;
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            if layout.get_collision(row, col).unwrap_or(0) == 0 {
+                continue;
+            }
+            let (cell_x, cell_y) = metatile_origin(row - region.y, col - region.x);
+            let (x, y) = (cell_x * scale, cell_y * scale);
+            let far = cell_size - scale;
+            fill_rect(image, x, y, cell_size, scale, WALL_OUTLINE_COLOR);
+            fill_rect(image, x, y + far, cell_size, scale, WALL_OUTLINE_COLOR);
+            fill_rect(image, x, y, scale, cell_size, WALL_OUTLINE_COLOR);
+            fill_rect(image, x + far, y, scale, cell_size, WALL_OUTLINE_COLOR);
+        }
+    }
+}
+
+/// Blends each cell with the [`ELEVATION_COLORS`] entry for its elevation, at half strength.
+pub fn tint_elevation(image: &mut RgbImage, layout: &Layout, region: Region, scale: u32) {
+    let cell_size = METATILE_DIMENSION * scale;
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let Some(metatile) = layout.get_metatile(row, col) else {
+                continue;
+            };
+            let tint = ELEVATION_COLORS[usize::from(metatile.elevation())];
+            let (cell_x, cell_y) = metatile_origin(row - region.y, col - region.x);
+            tint_rect(image, cell_x * scale, cell_y * scale, cell_size, tint);
+        }
+    }
+}
+
+/// Draws 1px lines along every tile boundary, grey within a metatile and black between
+/// metatiles. `image` has `scale` output pixels per map pixel.
+pub fn draw_tile_grid(image: &mut RgbImage, scale: u32) {
+    let spacing = TILE_DIMENSION * scale;
+    let tiles_per_metatile = METATILE_DIMENSION / TILE_DIMENSION;
+    let (width, height) = image.dimensions();
+    let color = |line: u32| {
+        if line.is_multiple_of(tiles_per_metatile) {
+            GRID_COLOR
+        } else {
+            TILE_GRID_COLOR
+        }
+    };
+    for line in 0..width.div_ceil(spacing) {
+        fill_rect(image, line * spacing, 0, 1, height, color(line));
+    }
+    for line in 0..height.div_ceil(spacing) {
+        fill_rect(image, 0, line * spacing, width, 1, color(line));
+    }
+}
+
+/// Encodes the collision values of `layout` as an indexed png with a `scale` x `scale`
+/// square per cell. Each pixel's palette index is the cell's collision value, colored with
+/// [`COLLISION_COLORS`], so the mask can be edited in an image editor.
+pub fn collision_mask_png(layout: &Layout, scale: u32) -> io::Result<Vec<u8>> {
+    let scale = scale.max(1);
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "collision mask too large");
+    let width = layout.width().checked_mul(scale).ok_or_else(too_large)?;
+    let height = layout.height().checked_mul(scale).ok_or_else(too_large)?;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let collision = layout.get_collision(y / scale, x / scale).unwrap_or(0);
+            pixels.push(collision);
+        }
+    }
+
+    let mut png_bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_bytes, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        COLLISION_COLORS
+            .iter()
+            .flat_map(|color| color.0)
+            .collect::<Vec<_>>(),
+    );
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(png_bytes)
+}
+
+/// Blends a `size` x `size` square at `(x, y)` with `tint` at half strength, clipped to
+/// `image`.
+fn tint_rect(image: &mut RgbImage, x: u32, y: u32, size: u32, tint: Rgb<u8>) {
+    for pixel_y in y..(y + size).min(image.height()) {
+        for pixel_x in x..(x + size).min(image.width()) {
+            let pixel = image.get_pixel_mut(pixel_x, pixel_y);
+            for (channel, tint) in pixel.0.iter_mut().zip(tint.0) {
+                *channel = ((u16::from(*channel) + u16::from(tint)) / 2) as u8;
+            }
+        }
+    }
+}
+
+/// A key listing a swatch and value for each color `overlay` can draw, or `None` if the
+/// overlay doesn't use colors to encode values.
+pub fn legend(overlay: Overlay, scale: u32, background: Rgb<u8>) -> Option<RgbImage> {
+    let colors: &[Rgb<u8>] = match overlay {
+        Overlay::CollisionLabels | Overlay::TileGrid | Overlay::WallOutlines => return None,
+        Overlay::Elevation => &ELEVATION_COLORS,
+    };
+    let pixel_size = LEGEND_PIXEL_SIZE * scale;
+    let swatch = LEGEND_SWATCH * scale;
+    let padding = LEGEND_PADDING * scale;
+    let widest_label = (colors.len() - 1).to_string();
+    let label_x = padding * 2 + swatch;
+    let label_height = text_height(pixel_size);
+    let row_height = swatch.max(label_height) + padding;
+    let width = label_x + text_width(&widest_label, pixel_size) + 2 * scale + padding;
+    let height = padding + row_height * colors.len() as u32;
+
+    let mut image = ImageBuffer::from_pixel(width, height, background);
+    for (value, color) in colors.iter().enumerate() {
+        let label = value.to_string();
+        let y = padding + row_height * value as u32;
+        let label_y = y + swatch.saturating_sub(label_height) / 2;
+        fill_rect(&mut image, padding, y, swatch, swatch, *color);
+        fill_rect(
+            &mut image,
+            label_x - scale,
+            label_y - scale,
+            text_width(&label, pixel_size) + 2 * scale,
+            label_height + 2 * scale,
+            LABEL_BACKGROUND,
+        );
+        draw_text(
+            &mut image,
+            &label,
+            label_x,
+            label_y,
+            pixel_size,
+            LABEL_COLOR,
+        );
+    }
+    Some(image)
+}
+
+/// Blows `image` up by `zoom` with a 1px grid around every source pixel, and writes the
+/// palette index from `indices` in each. Labels are left out if `zoom` is too small to fit
+/// them.
+pub fn pixel_grid(image: &RgbImage, indices: &GrayImage, zoom: u32) -> RgbImage {
+    let zoom = zoom.max(1);
+    let mut zoomed = image::imageops::resize(
+        image,
+        image.width() * zoom,
+        image.height() * zoom,
+        FilterType::Nearest,
+    );
+    let (width, height) = zoomed.dimensions();
+    for line in 0..image.width() {
+        fill_rect(&mut zoomed, line * zoom, 0, 1, height, GRID_COLOR);
+    }
+    for line in 0..image.height() {
+        fill_rect(&mut zoomed, 0, line * zoom, width, 1, GRID_COLOR);
+    }
+
+    let pixel_size = zoom / 12;
+    if pixel_size == 0 {
+        return zoomed;
+    }
+    for (x, y, index) in indices.enumerate_pixels() {
+        let label = index.0[0].to_string();
+        let width = text_width(&label, pixel_size);
+        let height = text_height(pixel_size);
+        let label_x = x * zoom + (zoom - width) / 2;
+        let label_y = y * zoom + (zoom - height) / 2;
+        fill_rect(
+            &mut zoomed,
+            label_x - pixel_size,
+            label_y - pixel_size,
+            width + 2 * pixel_size,
+            height + 2 * pixel_size,
+            LABEL_BACKGROUND,
+        );
+        draw_text(
+            &mut zoomed,
+            &label,
+            label_x,
+            label_y,
+            pixel_size,
+            LABEL_COLOR,
+        );
+    }
+    zoomed
+}
+
+/// Blends each of `cells`, given as `(row, col)` of the layout, with `color` at half strength.
+pub fn highlight_cells(
+    image: &mut RgbImage,
+    cells: &[(u32, u32)],
+    region: Region,
+    scale: u32,
+    color: Rgb<u8>,
+) {
+    let cell_size = METATILE_DIMENSION * scale;
+    for &(row, col) in cells {
+        if row < region.y || col < region.x {
+            continue;
+        }
+        let (cell_x, cell_y) = metatile_origin(row - region.y, col - region.x);
+        tint_rect(image, cell_x * scale, cell_y * scale, cell_size, color);
+    }
+}
+
+/// Lays out the tiles of a metatile as two 2x2 grids, the bottom layer on the left and the
+/// top on the right, each zoomed by `zoom` and labelled underneath with its tile id and
+/// palette number. `tiles` pairs each tile reference with its image as drawn, if it could
+/// be decoded. Transparent pixels of the top layer are left black.
+pub fn metatile_tile_sheet(tiles: &[(TileData, Option<RgbaImage>)], zoom: u32) -> RgbImage {
+    const ALPHA: usize = 3;
+    let zoom = zoom.max(1);
+    let tile_size = TILE_DIMENSION * zoom;
+    let pixel_size = (zoom / 4).max(1);
+    let label = |tile: &TileData| format!("T{} P{}", tile.tile_id(), tile.palette_number());
+    let cell_width = tiles
+        .iter()
+        .map(|(tile, _)| text_width(&label(tile), pixel_size))
+        .fold(tile_size, u32::max);
+    let cell_height = tile_size + text_height(pixel_size) + 2 * pixel_size;
+    let padding = zoom;
+    // An extra padding's width separates the two layers
+    let group_width = METATILE_TILE_GRID * (cell_width + padding) + padding;
+    let layers = tiles.len().div_ceil(TILES_PER_LAYER) as u32;
+    let width = layers * group_width;
+    let height = padding + METATILE_TILE_GRID * (cell_height + padding);
+
+    let mut sheet = ImageBuffer::from_pixel(width, height, LABEL_BACKGROUND);
+    for (index, (tile, image)) in tiles.iter().enumerate() {
+        let layer = (index / TILES_PER_LAYER) as u32;
+        let position = (index % TILES_PER_LAYER) as u32;
+        let (row, col) = (position / METATILE_TILE_GRID, position % METATILE_TILE_GRID);
+        let x = padding + layer * group_width + col * (cell_width + padding);
+        let y = padding + row * (cell_height + padding);
+        if let Some(image) = image {
+            for (pixel_x, pixel_y, pixel) in image.enumerate_pixels() {
+                if layer > 0 && pixel.0[ALPHA] == 0 {
+                    continue;
+                }
+                let [r, g, b, _] = pixel.0;
+                let (pixel_x, pixel_y) = (x + pixel_x * zoom, y + pixel_y * zoom);
+                fill_rect(&mut sheet, pixel_x, pixel_y, zoom, zoom, Rgb([r, g, b]));
+            }
+        }
+        let label_y = y + tile_size + pixel_size;
+        draw_text(
+            &mut sheet,
+            &label(tile),
+            x,
+            label_y,
+            pixel_size,
+            LABEL_COLOR,
+        );
+    }
+    sheet
+}
@@ -1,17 +1,123 @@
+#[cfg(feature = "fs")]
+use crate::gzip;
+use std::cell::OnceCell;
 use std::io;
-use std::path::Path;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Palette {
     inner: [(u8, u8, u8); 16],
 }
 
 impl Palette {
+    /// Converts the 15-bit BGR colors used by the GBA hardware.
+    pub fn from_bgr555(colors: [u16; 16]) -> Palette {
+        Palette {
+            inner: colors.map(|color| {
+                let channel = |shift: u16| (((color >> shift) & 0x1f) << 3) as u8;
+                (channel(0), channel(5), channel(10))
+            }),
+        }
+    }
+
+    pub fn from_colors(colors: [(u8, u8, u8); 16]) -> Palette {
+        Palette { inner: colors }
+    }
+
+    /// A predictable palette for tests: entries 1 to 15 step evenly from `from` to `to`, and
+    /// entry 0, the transparent index, is black.
+    pub fn gradient(from: (u8, u8, u8), to: (u8, u8, u8)) -> Palette {
+        let lerp = |from: u8, to: u8, step: i32| {
+            (i32::from(from) + (i32::from(to) - i32::from(from)) * step / 14) as u8
+        };
+        Palette {
+            inner: std::array::from_fn(|index| match index {
+                0 => (0, 0, 0),
+                _ => {
+                    let step = index as i32 - 1;
+                    (
+                        lerp(from.0, to.0, step),
+                        lerp(from.1, to.1, step),
+                        lerp(from.2, to.2, step),
+                    )
+                }
+            }),
+        }
+    }
+
     pub fn get(&self, entry: usize) -> &(u8, u8, u8) {
         &self.inner[entry]
     }
+
+    /// Whether every color is black, which is also what a file without a JASC-PAL header
+    /// parses to.
+    pub fn is_blank(&self) -> bool {
+        self.inner.iter().all(|color| *color == (0, 0, 0))
+    }
 }
 
+/// A palette slot whose file is only read and parsed the first time it's used.
+#[derive(Debug, Clone)]
+pub struct LazyPalette {
+    #[cfg(feature = "fs")]
+    path: Option<PathBuf>,
+    palette: OnceCell<Palette>,
+}
+
+impl LazyPalette {
+    /// Wraps a palette which has already been parsed.
+    pub fn loaded(palette: Palette) -> Self {
+        LazyPalette {
+            #[cfg(feature = "fs")]
+            path: None,
+            palette: OnceCell::from(palette),
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn from_path(path: PathBuf) -> Self {
+        LazyPalette {
+            path: Some(path),
+            palette: OnceCell::new(),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.palette.get().is_some()
+    }
+
+    /// Returns the palette, parsing it from its file first if this is the first access.
+    pub fn load(&self) -> io::Result<&Palette> {
+        if let Some(palette) = self.palette.get() {
+            return Ok(palette);
+        }
+        let palette = self.read()?;
+        Ok(self.palette.get_or_init(|| palette))
+    }
+
+    fn read(&self) -> io::Result<Palette> {
+        #[cfg(feature = "fs")]
+        if let Some(path) = &self.path {
+            return parse_palette(path);
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "palette has no file to load from",
+        ))
+    }
+}
+
+/// Palettes compare by their colors, loading them if needed.
+impl PartialEq for LazyPalette {
+    fn eq(&self, other: &Self) -> bool {
+        self.load().ok() == other.load().ok()
+    }
+}
+
+impl Eq for LazyPalette {}
+
+#[cfg(feature = "fs")]
 fn is_pal_file(entry: &Path) -> bool {
     entry
         .extension()
@@ -22,12 +128,17 @@ fn is_pal_file(entry: &Path) -> bool {
         == "pal"
 }
 
-fn palette_number(path: &Path) -> u32 {
-    path.file_stem().unwrap().to_str().unwrap().parse().unwrap()
+/// The slot a palette file is for, from a file name like `07.pal`.
+#[cfg(feature = "fs")]
+fn palette_number(path: &Path) -> Option<u32> {
+    path.file_stem()?.to_str()?.parse().ok()
 }
 
-pub fn parse_all_palettes(path: impl AsRef<Path>) -> io::Result<Vec<Palette>> {
-    let mut palettes = std::fs::read_dir(path)?
+/// The palette files in a directory in slot order. Files not named for a slot, such as
+/// `custom.pal` or `00_backup.pal`, are skipped with a warning.
+#[cfg(feature = "fs")]
+fn palette_paths(path: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut palette_paths = std::fs::read_dir(path)?
         .filter_map(|entry| {
             if let Ok(entry) = entry {
                 if is_pal_file(&entry.path()) {
@@ -39,33 +150,87 @@ pub fn parse_all_palettes(path: impl AsRef<Path>) -> io::Result<Vec<Palette>> {
                 None
             }
         })
-        .map(|palette_path| {
-            parse_palette(&palette_path).map(|palette| (palette, palette_number(&palette_path)))
+        .filter_map(|palette_path| match palette_number(&palette_path) {
+            Some(number) => Some((number, palette_path)),
+            None => {
+                log::warn!(
+                    "Skipping {}, palette files should be named by their slot number",
+                    palette_path.display()
+                );
+                None
+            }
         })
-        .collect::<io::Result<Vec<(Palette, u32)>>>()?;
-    palettes.sort_by(|(_, a), (_, b)| a.cmp(b));
-    Ok(palettes.into_iter().map(|(palette, _)| palette).collect())
+        .collect::<Vec<_>>();
+    palette_paths.sort_by_key(|(number, _)| *number);
+    Ok(palette_paths.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(feature = "fs")]
+pub fn parse_all_palettes(path: impl AsRef<Path>) -> io::Result<Vec<Palette>> {
+    palette_paths(path)?.iter().map(parse_palette).collect()
 }
 
+/// Finds every palette file in a directory without parsing any of them yet.
+#[cfg(feature = "fs")]
+pub fn lazy_palettes(path: impl AsRef<Path>) -> io::Result<Vec<LazyPalette>> {
+    Ok(palette_paths(path)?
+        .into_iter()
+        .map(LazyPalette::from_path)
+        .collect())
+}
+
+#[cfg(feature = "fs")]
 fn parse_palette(path: impl AsRef<Path>) -> io::Result<Palette> {
-    // Parses a JASC-PAL file
-    let palette_contents = std::fs::read_to_string(&path)?;
-    let mut lines = palette_contents.lines();
+    log::debug!("Loading palette {}", path.as_ref().display());
+    let palette_contents = gzip::read_to_string(&path)?;
+    let palette = parse_palette_contents(&palette_contents)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.as_ref().display())))?;
+    if palette.is_blank() {
+        log::warn!("Palette {} is entirely black", path.as_ref().display());
+    }
+    Ok(palette)
+}
+
+/// Parses the contents of a JASC-PAL file. A leading byte order mark and whitespace around
+/// each line, such as the `\r` of a CRLF file, are ignored. A header other than a 16 color
+/// JASC-PAL one, missing entries, or entries which aren't three values from 0 to 255, are
+/// `InvalidData` errors.
+pub fn parse_palette_contents(palette_contents: &str) -> io::Result<Palette> {
+    let palette_contents = palette_contents
+        .strip_prefix('\u{feff}')
+        .unwrap_or(palette_contents);
+    let mut lines = palette_contents.lines().map(str::trim);
     let mut palette_data = [(0, 0, 0); 16];
-    if let (Some("JASC-PAL"), Some("0100"), Some("16")) = (lines.next(), lines.next(), lines.next())
-    {
-        log::debug!("Loading palette {}", path.as_ref().display());
-        for (palette_id, palette_item) in palette_data.iter_mut().enumerate() {
-            let palette_values = lines
-                .next()
-                .unwrap()
-                .split_ascii_whitespace()
-                .map(|value| value.parse::<u8>().unwrap())
-                .collect::<Vec<_>>();
-            assert_eq!(palette_values.len(), 3);
-            log::debug!("Entry {palette_id}: {palette_values:?}");
-            *palette_item = (palette_values[0], palette_values[1], palette_values[2]);
+    let header = (lines.next(), lines.next(), lines.next());
+    let (Some("JASC-PAL"), Some("0100"), Some("16")) = header else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected a 16 color JASC-PAL header, found {header:?}"),
+        ));
+    };
+    for (palette_id, palette_item) in palette_data.iter_mut().enumerate() {
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("palette entry {palette_id} {reason}"),
+            )
+        };
+        let line = lines
+            .next()
+            .ok_or_else(|| invalid("is missing".to_string()))?;
+        let palette_values = line
+            .split_ascii_whitespace()
+            .map(|value| value.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| invalid(format!("{line:?} has a bad value: {err}")))?;
+        if palette_values.len() != 3 {
+            return Err(invalid(format!(
+                "{line:?} has {} values instead of 3",
+                palette_values.len()
+            )));
         }
+        log::trace!("Entry {palette_id}: {palette_values:?}");
+        *palette_item = (palette_values[0], palette_values[1], palette_values[2]);
     }
 
     Ok(Palette {
@@ -0,0 +1,974 @@
+use crate::dimensions::{metatile_origin, METATILE_DIMENSION};
+use crate::font::{draw_text, text_height, text_width};
+use crate::map::{Layout, MapMetatileData};
+use crate::overlay::{
+    draw_collision_labels, draw_tile_grid, draw_wall_outlines, legend, tint_elevation,
+};
+use crate::tileset::{LayoutTileset, MetatileRenderError};
+use image::{
+    codecs::png::{self, PngEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageBuffer, ImageResult, Pixel, Rgb, RgbImage, RgbaImage,
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// Elevation the game gives bridges and other cells walkable both on top and underneath.
+pub const BRIDGE_ELEVATION: u8 = 15;
+
+/// Opacity of the top layer of a bridge cell with [`RenderOptionsBuilder::xray_bridges`].
+const XRAY_OPACITY: u8 = 112;
+
+/// Side of a square of the [`RenderOptionsBuilder::alpha_checker`] pattern in pixels. It
+/// divides a metatile evenly, so the pattern lines up across cells.
+const CHECKER_SQUARE: u32 = 4;
+
+/// A rectangle of a layout, measured in metatiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The region covering all of `layout`.
+    pub fn full(layout: &Layout) -> Self {
+        Region {
+            x: 0,
+            y: 0,
+            width: layout.width(),
+            height: layout.height(),
+        }
+    }
+
+    /// Whether the cell at `(row, col)` of the layout lies inside this region.
+    pub fn contains(&self, row: u32, col: u32) -> bool {
+        let within = |value: u32, start: u32, len: u32| {
+            value.checked_sub(start).is_some_and(|offset| offset < len)
+        };
+        within(row, self.y, self.height) && within(col, self.x, self.width)
+    }
+
+    /// Shrinks this region of `layout` to the smallest rectangle containing every cell
+    /// whose metatile differs from the most common metatile along the region's edge. A
+    /// region made entirely of that metatile is returned unchanged.
+    pub fn autocrop(self, layout: &Layout) -> Self {
+        let region = self.clip_to(layout);
+        let metatile_id = |row, col| {
+            layout
+                .get_metatile(row, col)
+                .map(|metatile| metatile.metatile_id())
+        };
+        let mut edge_counts = HashMap::new();
+        for row in region.y..region.y + region.height {
+            for col in region.x..region.x + region.width {
+                let on_edge = row == region.y
+                    || col == region.x
+                    || row + 1 == region.y + region.height
+                    || col + 1 == region.x + region.width;
+                if on_edge {
+                    *edge_counts.entry(metatile_id(row, col)).or_insert(0) += 1;
+                }
+            }
+        }
+        let Some((fill, _)) = edge_counts
+            .into_iter()
+            .max_by_key(|(id, count)| (*count, *id))
+        else {
+            return region;
+        };
+
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for row in region.y..region.y + region.height {
+            for col in region.x..region.x + region.width {
+                if metatile_id(row, col) == fill {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    None => (col, row, col, row),
+                    Some((left, top, right, bottom)) => {
+                        (left.min(col), top.min(row), right.max(col), bottom.max(row))
+                    }
+                });
+            }
+        }
+        match bounds {
+            Some((left, top, right, bottom)) => Region {
+                x: left,
+                y: top,
+                width: right - left + 1,
+                height: bottom - top + 1,
+            },
+            None => region,
+        }
+    }
+
+    /// Divides this region into a grid of `rows` by `cols` pages, returned in row-major order
+    /// as `(page_row, page_col, region)`. Pages are as even as possible, and any row or column
+    /// of pages which would be empty is left out.
+    pub fn split(self, rows: u32, cols: u32) -> Vec<(u32, u32, Region)> {
+        let page_height = self.height.div_ceil(rows.max(1)).max(1);
+        let page_width = self.width.div_ceil(cols.max(1)).max(1);
+        let mut pages = vec![];
+        for page_row in 0..rows {
+            let y = page_row * page_height;
+            if y >= self.height {
+                break;
+            }
+            for page_col in 0..cols {
+                let x = page_col * page_width;
+                if x >= self.width {
+                    break;
+                }
+                pages.push((
+                    page_row,
+                    page_col,
+                    Region {
+                        x: self.x + x,
+                        y: self.y + y,
+                        width: page_width.min(self.width - x),
+                        height: page_height.min(self.height - y),
+                    },
+                ));
+            }
+        }
+        pages
+    }
+
+    fn clip_to(self, layout: &Layout) -> Self {
+        let x = self.x.min(layout.width());
+        let y = self.y.min(layout.height());
+        Region {
+            x,
+            y,
+            width: self.width.min(layout.width() - x),
+            height: self.height.min(layout.height() - y),
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses a region written as `x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid region {s}: {err}"))?;
+        let [x, y, width, height] = values[..] else {
+            return Err(format!("invalid region {s}, expected x,y,width,height"));
+        };
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+/// An RGB color, written as `r,g,b` on the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses a color written as `r,g,b` with each channel in 0-255.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid color {s}: {err}"))?;
+        let [r, g, b] = values[..] else {
+            return Err(format!("invalid color {s}, expected r,g,b"));
+        };
+        Ok(Color { r, g, b })
+    }
+}
+
+impl From<Color> for Rgb<u8> {
+    fn from(color: Color) -> Self {
+        Rgb([color.r, color.g, color.b])
+    }
+}
+
+/// Annotations which can be drawn on top of a rendered map. Deserializes from kebab case
+/// names such as `collision-labels`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overlay {
+    /// The collision value of each impassable cell, written as a number
+    CollisionLabels,
+    /// Each cell tinted with a color for its elevation
+    Elevation,
+    /// Lines along the edges of every 8x8 tile, with metatile edges drawn darker
+    TileGrid,
+    /// An outline around each impassable cell
+    WallOutlines,
+}
+
+/// Controls how [`render_layout`] turns a layout into an image. Build one with
+/// [`RenderOptions::builder`], or use the default to render the whole map at 1x.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    scale: u32,
+    region: Option<Region>,
+    grayscale: bool,
+    background: Color,
+    overlays: Vec<Overlay>,
+    legend: bool,
+    bottom_only: bool,
+    strict: bool,
+    raw_indices: bool,
+    xray_bridges: bool,
+    alpha_checker: bool,
+    remap: HashMap<u16, u16>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            scale: 1,
+            region: None,
+            grayscale: false,
+            background: Color::default(),
+            overlays: vec![],
+            legend: false,
+            bottom_only: false,
+            strict: false,
+            raw_indices: false,
+            xray_bridges: false,
+            alpha_checker: false,
+            remap: HashMap::new(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn builder() -> RenderOptionsBuilder {
+        RenderOptionsBuilder::default()
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// The metatile drawn for a cell whose metatile id is `metatile_id`, after any
+    /// [`RenderOptionsBuilder::remap`].
+    pub fn remapped(&self, metatile_id: u16) -> u16 {
+        self.remap.get(&metatile_id).copied().unwrap_or(metatile_id)
+    }
+
+    /// The part of `layout` these options render, after clipping to its bounds.
+    pub fn rendered_region(&self, layout: &Layout) -> Region {
+        self.region
+            .map(|region| region.clip_to(layout))
+            .unwrap_or_else(|| Region::full(layout))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptionsBuilder {
+    options: RenderOptions,
+}
+
+impl RenderOptionsBuilder {
+    /// Integer upscaling factor applied with nearest-neighbour sampling, defaults to 1.
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.options.scale = scale.max(1);
+        self
+    }
+
+    /// Only render this part of the layout, clipped to the layout's bounds.
+    pub fn region(mut self, region: impl Into<Option<Region>>) -> Self {
+        self.options.region = region.into();
+        self
+    }
+
+    /// Output the luminance of each color as a grayscale image instead of RGB.
+    pub fn grayscale(mut self, grayscale: bool) -> Self {
+        self.options.grayscale = grayscale;
+        self
+    }
+
+    /// Color of any part of the map not covered by a metatile, and of pixels where neither
+    /// of a metatile's layers draws an opaque color. Defaults to black.
+    pub fn background(mut self, background: Color) -> Self {
+        self.options.background = background;
+        self
+    }
+
+    /// Draw `overlay` on top of the rendered map.
+    pub fn overlay(mut self, overlay: Overlay) -> Self {
+        if !self.options.overlays.contains(&overlay) {
+            self.options.overlays.push(overlay);
+        }
+        self
+    }
+
+    /// Append a key for any color coded overlays to the right of the map.
+    pub fn legend(mut self, legend: bool) -> Self {
+        self.options.legend = legend;
+        self
+    }
+
+    /// Leave out the tiles of each metatile drawn above the player, see
+    /// [`LayoutTileset::get_metatile_background_image`].
+    pub fn bottom_only(mut self, bottom_only: bool) -> Self {
+        self.options.bottom_only = bottom_only;
+        self
+    }
+
+    /// Fail the render on any metatile which can't be drawn completely, instead of logging
+    /// the problem and leaving that part of the map blank.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Draw each pixel's palette index as a gray level, from black for 0 to white for 15,
+    /// instead of its color. Separates tile decoding problems from palette problems.
+    pub fn raw_indices(mut self, raw_indices: bool) -> Self {
+        self.options.raw_indices = raw_indices;
+        self
+    }
+
+    /// Draw the top layer of cells at [`BRIDGE_ELEVATION`] partly transparent over their
+    /// bottom layer. The crate doesn't know what's really beneath a bridge, so this only
+    /// approximates it, but it makes elevated structures stand out from the ground.
+    pub fn xray_bridges(mut self, xray_bridges: bool) -> Self {
+        self.options.xray_bridges = xray_bridges;
+        self
+    }
+
+    /// Fill pixels where neither of a metatile's layers draws an opaque color with a light
+    /// and dark gray checkerboard instead of the background color, to check which parts of
+    /// the map are transparent.
+    pub fn alpha_checker(mut self, alpha_checker: bool) -> Self {
+        self.options.alpha_checker = alpha_checker;
+        self
+    }
+
+    /// Draw metatile `to` wherever the layout has metatile `from`, without changing the
+    /// layout. Ids aren't remapped more than once, so remaps can swap ids.
+    pub fn remap(mut self, from: u16, to: u16) -> Self {
+        self.options.remap.insert(from, to);
+        self
+    }
+
+    pub fn build(self) -> RenderOptions {
+        self.options
+    }
+}
+
+/// The `(width, height)` in pixels of the image [`render_layout`] would produce, or an error
+/// if the image would be too large to allocate.
+pub fn output_size(layout: &Layout, options: &RenderOptions) -> io::Result<(u32, u32)> {
+    let region = options.rendered_region(layout);
+    image_size(region.width, region.height, options.scale)
+}
+
+/// The `(width, height)` in pixels of an RGB image `width` by `height` metatiles at `scale`,
+/// or an error if the image would be too large to allocate.
+pub fn image_size(width: u32, height: u32, scale: u32) -> io::Result<(u32, u32)> {
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a {width}x{height} metatile render at {scale}x scale is too large"),
+        )
+    };
+    let pixels = |metatiles: u32| {
+        metatiles
+            .checked_mul(METATILE_DIMENSION)
+            .and_then(|pixels| pixels.checked_mul(scale))
+    };
+    let width = pixels(width).ok_or_else(too_large)?;
+    let height = pixels(height).ok_or_else(too_large)?;
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|area| area.checked_mul(3))
+        .ok_or_else(too_large)?;
+    Ok((width, height))
+}
+
+/// Renders an already loaded `layout` as described by `options`. Fails if the image would
+/// be too large, see [`output_size`], and otherwise only in strict mode, see
+/// [`RenderOptionsBuilder::strict`].
+pub fn render_layout(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    options: &RenderOptions,
+) -> io::Result<DynamicImage> {
+    render_layout_with_unresolved(layout, tileset, options).map(|(image, _)| image)
+}
+
+/// Like [`render_layout`], but also returns the number of cells which couldn't be drawn and
+/// were left as the background.
+pub fn render_layout_with_unresolved(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    options: &RenderOptions,
+) -> io::Result<(DynamicImage, usize)> {
+    output_size(layout, options)?;
+    let region = options.rendered_region(layout);
+    if options.strict {
+        check_region(layout, tileset, region, options)?;
+    }
+    log::info!(
+        "Rendering {}x{} metatiles at ({}, {})",
+        region.width,
+        region.height,
+        region.x,
+        region.y
+    );
+    let (mut map_image, unresolved) = render_region(layout, tileset, region, options)?;
+
+    if options.scale > 1 {
+        map_image = image::imageops::resize(
+            &map_image,
+            map_image.width() * options.scale,
+            map_image.height() * options.scale,
+            FilterType::Nearest,
+        );
+    }
+
+    for overlay in &options.overlays {
+        match overlay {
+            Overlay::CollisionLabels => {
+                draw_collision_labels(&mut map_image, layout, region, options.scale)
+            }
+            Overlay::Elevation => tint_elevation(&mut map_image, layout, region, options.scale),
+            Overlay::TileGrid => draw_tile_grid(&mut map_image, options.scale),
+            Overlay::WallOutlines => {
+                draw_wall_outlines(&mut map_image, layout, region, options.scale)
+            }
+        }
+    }
+
+    if options.legend {
+        for overlay in &options.overlays {
+            if let Some(legend) = legend(*overlay, options.scale, options.background.into()) {
+                map_image = append_right(&map_image, &legend, options.background.into());
+            }
+        }
+    }
+
+    let map_image = if options.grayscale {
+        DynamicImage::ImageLuma8(image::imageops::grayscale(&map_image))
+    } else {
+        DynamicImage::ImageRgb8(map_image)
+    };
+    Ok((map_image, unresolved))
+}
+
+/// Returns the `(row, col, metatile_id, error)` of every cell [`render_layout`] would fail
+/// to draw with `options`, in row-major order. The id is the one drawn, after any remap.
+/// Each distinct metatile is only drawn once.
+pub fn unresolved_cells(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    options: &RenderOptions,
+) -> Vec<(u32, u32, u16, MetatileRenderError)> {
+    let region = options.rendered_region(layout);
+    let mut errors = HashMap::new();
+    let mut unresolved = vec![];
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let Some(metatile_data) = layout.get_metatile(row, col) else {
+                continue;
+            };
+            let metatile_id = options.remapped(metatile_data.metatile_id());
+            let error = *errors
+                .entry(metatile_id)
+                .or_insert_with(|| draw_metatile(tileset, metatile_id, options).err());
+            if let Some(error) = error {
+                unresolved.push((row, col, metatile_id, error));
+            }
+        }
+    }
+    unresolved
+}
+
+/// Checks every distinct metatile in `region` of `layout` can be drawn completely with
+/// `options`, by drawing each once. Only the tiles and palettes `options` actually draw are
+/// checked, so e.g. a broken top layer is fine when rendering the bottom layer only.
+fn check_region(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    region: Region,
+    options: &RenderOptions,
+) -> io::Result<()> {
+    let mut checked = HashSet::new();
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let Some(metatile_data) = layout.get_metatile(row, col) else {
+                continue;
+            };
+            let metatile_id = options.remapped(metatile_data.metatile_id());
+            if !checked.insert(metatile_id) {
+                continue;
+            }
+            if let Err(err) = draw_metatile(tileset, metatile_id, options) {
+                return Err(io::Error::new(
+                    io::Error::from(err).kind(),
+                    format!("at ({col}, {row}): metatile {metatile_id}: {err}"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders `layout` at one pixel per cell, colored with the average of the cell's metatile.
+/// Each metatile id is only drawn once. Metatiles which can't be drawn are left black.
+pub fn render_overview(layout: &Layout, tileset: &LayoutTileset) -> RgbImage {
+    let mut colors: HashMap<u16, Rgb<u8>> = HashMap::new();
+    ImageBuffer::from_fn(layout.width(), layout.height(), |col, row| {
+        let Some(metatile_data) = layout.get_metatile(row, col) else {
+            return Rgb([0, 0, 0]);
+        };
+        *colors
+            .entry(metatile_data.metatile_id())
+            .or_insert_with(
+                || match tileset.get_metatile_image(metatile_data.metatile_id()) {
+                    Ok(metatile_image) => average_color(&metatile_image),
+                    Err(err) => {
+                        log::error!(
+                            "Failed to get metatile image at coordinate: ({col}, {row}): {err}"
+                        );
+                        Rgb([0, 0, 0])
+                    }
+                },
+            )
+    })
+}
+
+fn average_color(image: &RgbImage) -> Rgb<u8> {
+    let mut sums = [0u64; 3];
+    for pixel in image.pixels() {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += u64::from(channel);
+        }
+    }
+    let count = u64::from(image.width() * image.height()).max(1);
+    Rgb(sums.map(|sum| (sum / count) as u8))
+}
+
+/// Shrinks `image` to fit within `max_size` pixels on each side, keeping its aspect ratio.
+/// Images which already fit are returned unchanged.
+pub fn thumbnail(image: &RgbImage, max_size: u32) -> RgbImage {
+    if image.width() <= max_size && image.height() <= max_size {
+        return image.clone();
+    }
+    let scale = max_size as f64 / image.width().max(image.height()) as f64;
+    let width = ((image.width() as f64 * scale).round() as u32).max(1);
+    let height = ((image.height() as f64 * scale).round() as u32).max(1);
+    image::imageops::thumbnail(image, width, height)
+}
+
+/// Arranges `images` in a grid `columns` wide, each with its name written underneath.
+/// Every cell is padded to the size of the largest image and label.
+pub fn contact_sheet(images: &[(String, RgbImage)], columns: u32, background: Color) -> RgbImage {
+    const PADDING: u32 = 4;
+    const LABEL_PIXEL_SIZE: u32 = 1;
+    const LABEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+    let columns = columns.max(1);
+    let rows = images.len().div_ceil(columns as usize) as u32;
+    let label_height = text_height(LABEL_PIXEL_SIZE);
+    let cell_width = images
+        .iter()
+        .map(|(name, image)| image.width().max(text_width(name, LABEL_PIXEL_SIZE)))
+        .max()
+        .unwrap_or(0)
+        + PADDING;
+    let cell_height = images
+        .iter()
+        .map(|(_, image)| image.height())
+        .max()
+        .unwrap_or(0)
+        + PADDING
+        + label_height
+        + PADDING;
+
+    let mut sheet = ImageBuffer::from_pixel(
+        PADDING + cell_width * columns,
+        PADDING + cell_height * rows,
+        background.into(),
+    );
+    for (idx, (name, image)) in images.iter().enumerate() {
+        let x = PADDING + cell_width * (idx as u32 % columns);
+        let y = PADDING + cell_height * (idx as u32 / columns);
+        blit_metatile(&mut sheet, image, x, y);
+        draw_text(
+            &mut sheet,
+            name,
+            x,
+            y + image.height() + PADDING,
+            LABEL_PIXEL_SIZE,
+            LABEL_COLOR,
+        );
+    }
+    sheet
+}
+
+/// The side of a map another map is connected on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            _ => Err(format!(
+                "unknown direction {s}, expected up, down, left or right"
+            )),
+        }
+    }
+}
+
+/// A connected map drawn around another by [`onion_skin`].
+#[derive(Debug, Clone)]
+pub struct Neighbor {
+    pub direction: Direction,
+    /// How far along the shared edge the neighbor starts, in metatiles, as in a map
+    /// connection's offset
+    pub offset: i32,
+    pub image: RgbImage,
+}
+
+/// Surrounds `map` with a `margin` metatile wide strip of each of its `neighbors`, blended
+/// with the background at `opacity` out of 255 so the map itself stands out. Neighbor
+/// images must be rendered at the same `scale` as `map`.
+pub fn onion_skin(
+    map: &RgbImage,
+    neighbors: &[Neighbor],
+    margin: u32,
+    scale: u32,
+    opacity: u8,
+    background: Color,
+) -> RgbImage {
+    let cell = i64::from(METATILE_DIMENSION * scale);
+    let margin_pixels = margin * METATILE_DIMENSION * scale;
+    let mut canvas = ImageBuffer::from_pixel(
+        map.width() + 2 * margin_pixels,
+        map.height() + 2 * margin_pixels,
+        background.into(),
+    );
+    for neighbor in neighbors {
+        let along = i64::from(neighbor.offset) * cell;
+        let (x, y) = match neighbor.direction {
+            Direction::Up => (along, -i64::from(neighbor.image.height())),
+            Direction::Down => (along, i64::from(map.height())),
+            Direction::Left => (-i64::from(neighbor.image.width()), along),
+            Direction::Right => (i64::from(map.width()), along),
+        };
+        blend_onto(
+            &mut canvas,
+            &neighbor.image,
+            x + i64::from(margin_pixels),
+            y + i64::from(margin_pixels),
+            opacity,
+        );
+    }
+    blit_metatile(&mut canvas, map, margin_pixels, margin_pixels);
+    canvas
+}
+
+/// Mixes `src` into `dest` at `opacity` out of 255 with its top-left corner at `(x, y)`,
+/// which may be off either edge. Pixels outside `dest` are skipped.
+fn blend_onto(dest: &mut RgbImage, src: &RgbImage, x: i64, y: i64, opacity: u8) {
+    let opacity = u16::from(opacity);
+    for (src_x, src_y, pixel) in src.enumerate_pixels() {
+        let (Ok(dest_x), Ok(dest_y)) = (
+            u32::try_from(x + i64::from(src_x)),
+            u32::try_from(y + i64::from(src_y)),
+        ) else {
+            continue;
+        };
+        if dest_x >= dest.width() || dest_y >= dest.height() {
+            continue;
+        }
+        let dest_pixel = dest.get_pixel_mut(dest_x, dest_y);
+        for (channel, src) in dest_pixel.0.iter_mut().zip(pixel.0) {
+            *channel =
+                ((u16::from(src) * opacity + u16::from(*channel) * (255 - opacity)) / 255) as u8;
+        }
+    }
+}
+
+/// Places `right` beside `left`, filling any space left below the shorter one.
+fn append_right(left: &RgbImage, right: &RgbImage, background: Rgb<u8>) -> RgbImage {
+    let mut combined = ImageBuffer::from_pixel(
+        left.width() + right.width(),
+        left.height().max(right.height()),
+        background,
+    );
+    blit_metatile(&mut combined, left, 0, 0);
+    blit_metatile(&mut combined, right, left.width(), 0);
+    combined
+}
+
+/// Renders `region` of `layout` at 1x, along with the number of cells which couldn't be drawn.
+fn render_region(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    region: Region,
+    options: &RenderOptions,
+) -> io::Result<(RgbImage, usize)> {
+    image_size(region.width, region.height, 1)?;
+    let mut map_image: RgbImage = ImageBuffer::from_pixel(
+        METATILE_DIMENSION * region.width,
+        METATILE_DIMENSION * region.height,
+        options.background.into(),
+    );
+
+    let mut unresolved = 0;
+    for row in region.y..region.y + region.height {
+        for col in region.x..region.x + region.width {
+            let metatile_data = layout.get_metatile(row, col).unwrap();
+            let (metatile_left_pixel, metatile_top_pixel) =
+                metatile_origin(row - region.y, col - region.x);
+            log::trace!("Metatile id: {}", metatile_data.metatile_id());
+            match draw_cell(tileset, metatile_data, options) {
+                Ok(metatile_image) => blit_metatile(
+                    &mut map_image,
+                    &metatile_image,
+                    metatile_left_pixel,
+                    metatile_top_pixel,
+                ),
+                Err(err) => {
+                    unresolved += 1;
+                    log::error!("Failed to get metatile image at coordinate: ({col}, {row}): {err}")
+                }
+            }
+        }
+    }
+
+    Ok((map_image, unresolved))
+}
+
+/// Renders the layout's border block, the metatiles the game repeats around the map. Border
+/// cells use the same metatile ids as the map so they resolve through both tilesets the
+/// same way. Of `options`, the scale and the metatile drawing settings (background, bottom
+/// only, raw indices, alpha checker and remap) apply; the layout settings (region, grayscale,
+/// overlays, legend, strict and x-ray bridges) do not. Fails if the image would be too
+/// large, see [`image_size`].
+pub fn render_border(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    options: &RenderOptions,
+) -> io::Result<RgbImage> {
+    let border = layout.border();
+    image_size(border.width(), border.height(), options.scale)?;
+    let mut border_image: RgbImage = ImageBuffer::from_pixel(
+        METATILE_DIMENSION * border.width(),
+        METATILE_DIMENSION * border.height(),
+        options.background.into(),
+    );
+    for (row, col, metatile_data) in border.iter() {
+        let (x, y) = metatile_origin(row, col);
+        match draw_metatile(
+            tileset,
+            options.remapped(metatile_data.metatile_id()),
+            options,
+        ) {
+            Ok(metatile_image) => blit_metatile(&mut border_image, &metatile_image, x, y),
+            Err(err) => log::error!(
+                "Failed to get border metatile image at coordinate: ({col}, {row}): {err}"
+            ),
+        }
+    }
+
+    if options.scale > 1 {
+        border_image = image::imageops::resize(
+            &border_image,
+            border_image.width() * options.scale,
+            border_image.height() * options.scale,
+            FilterType::Nearest,
+        );
+    }
+    Ok(border_image)
+}
+
+/// Draws the metatile of a map cell, seeing through it if it's a bridge and
+/// [`RenderOptionsBuilder::xray_bridges`] is set.
+fn draw_cell(
+    tileset: &LayoutTileset,
+    metatile_data: MapMetatileData,
+    options: &RenderOptions,
+) -> Result<RgbImage, MetatileRenderError> {
+    let metatile_id = options.remapped(metatile_data.metatile_id());
+    let metatile_image = draw_metatile(tileset, metatile_id, options)?;
+    let Some(opacity) = xray_opacity(metatile_data.elevation()) else {
+        return Ok(metatile_image);
+    };
+    if !options.xray_bridges || options.bottom_only || options.raw_indices {
+        return Ok(metatile_image);
+    }
+    let beneath = tileset.get_metatile_background_image_rgba(metatile_id)?;
+    let mut beneath = over_background(&beneath, options);
+    blend_onto(&mut beneath, &metatile_image, 0, 0, opacity);
+    Ok(beneath)
+}
+
+/// Opacity out of 255 used by [`RenderOptionsBuilder::xray_bridges`] for a cell at
+/// `elevation`, or `None` if cells at that elevation are drawn solid.
+pub fn xray_opacity(elevation: u8) -> Option<u8> {
+    (elevation == BRIDGE_ELEVATION).then_some(XRAY_OPACITY)
+}
+
+fn draw_metatile(
+    tileset: &LayoutTileset,
+    metatile_id: u16,
+    options: &RenderOptions,
+) -> Result<RgbImage, MetatileRenderError> {
+    if options.raw_indices {
+        let indices = tileset.metatile_color_indices(metatile_id)?;
+        return Ok(ImageBuffer::from_fn(
+            indices.width(),
+            indices.height(),
+            |x, y| {
+                let level = indices.get_pixel(x, y).0[0] * 17;
+                Rgb([level, level, level])
+            },
+        ));
+    }
+    let metatile_image = if options.bottom_only {
+        tileset.get_metatile_background_image_rgba(metatile_id)?
+    } else {
+        tileset.get_metatile_image_rgba(metatile_id)?
+    };
+    Ok(over_background(&metatile_image, options))
+}
+
+/// Flattens a metatile drawn with transparency, filling pixels which no layer drew with
+/// the background color, or the checkerboard of [`RenderOptionsBuilder::alpha_checker`].
+fn over_background(metatile_image: &RgbaImage, options: &RenderOptions) -> RgbImage {
+    let background = Rgb::from(options.background);
+    ImageBuffer::from_fn(metatile_image.width(), metatile_image.height(), |x, y| {
+        let [r, g, b, alpha] = metatile_image.get_pixel(x, y).0;
+        match alpha {
+            0 if options.alpha_checker => checker_color(x, y),
+            0 => background,
+            _ => Rgb([r, g, b]),
+        }
+    })
+}
+
+fn checker_color(x: u32, y: u32) -> Rgb<u8> {
+    if (x / CHECKER_SQUARE + y / CHECKER_SQUARE).is_multiple_of(2) {
+        Rgb([204, 204, 204])
+    } else {
+        Rgb([153, 153, 153])
+    }
+}
+
+/// How hard to compress png output, trading file size for encoding time. Written as
+/// `fast`, `default` or `best` on the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl FromStr for PngCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(PngCompression::Fast),
+            "default" => Ok(PngCompression::Default),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(format!(
+                "unknown png compression {s}, expected fast, default or best"
+            )),
+        }
+    }
+}
+
+/// Encodes `image` as a png into `writer` at the given compression level. The default
+/// level is the `image` crate's default, as used by `save`, which already deflates at its
+/// fastest setting. Fast additionally uses one fixed filter instead of trying every filter
+/// on each row.
+pub fn write_png(
+    image: &DynamicImage,
+    writer: impl Write,
+    compression: PngCompression,
+) -> ImageResult<()> {
+    let (compression, filter) = match compression {
+        PngCompression::Fast => (png::CompressionType::Fast, png::FilterType::Paeth),
+        PngCompression::Default => (png::CompressionType::default(), png::FilterType::default()),
+        PngCompression::Best => (png::CompressionType::Best, png::FilterType::Adaptive),
+    };
+    image.write_with_encoder(PngEncoder::new_with_quality(writer, compression, filter))
+}
+
+/// Renders `layout` and encodes it as a png in memory rather than writing it to disk, as
+/// [`write_png`] would with `compression`.
+pub fn render_to_png_bytes(
+    layout: &Layout,
+    tileset: &LayoutTileset,
+    options: &RenderOptions,
+    compression: PngCompression,
+) -> ImageResult<Vec<u8>> {
+    let mut png_bytes = Vec::new();
+    write_png(
+        &render_layout(layout, tileset, options)?,
+        &mut png_bytes,
+        compression,
+    )?;
+    Ok(png_bytes)
+}
+
+/// Copies `src` into `dest` with its top-left corner at pixel `(x, y)`, one row at a time.
+/// Any part of `src` that falls outside of `dest` is clipped rather than panicking.
+pub fn blit_metatile<P: Pixel>(
+    dest: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    src: &ImageBuffer<P, Vec<P::Subpixel>>,
+    x: u32,
+    y: u32,
+) {
+    let channels = P::CHANNEL_COUNT as usize;
+    if x >= dest.width() || y >= dest.height() {
+        return;
+    }
+    let copy_width = src.width().min(dest.width() - x) as usize * channels;
+    let copy_height = src.height().min(dest.height() - y) as usize;
+    let src_stride = src.width() as usize * channels;
+    let dest_stride = dest.width() as usize * channels;
+
+    let src_data = src.as_raw();
+    let dest_data: &mut [P::Subpixel] = dest;
+    for row in 0..copy_height {
+        let src_start = row * src_stride;
+        let dest_start = (y as usize + row) * dest_stride + x as usize * channels;
+        dest_data[dest_start..dest_start + copy_width]
+            .copy_from_slice(&src_data[src_start..src_start + copy_width]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Reading layouts straight out of a FireRed/LeafGreen ROM image instead of a pret checkout.
+//!
+//! Only layout headers, tileset headers, blockdata, borders and palettes are read, by
+//! following the pointers in the ROM's `MapLayout` and `Tileset` structs, and decoded with
+//! the same parsers used for pret assets. Metatiles and the LZ77 compressed tile graphics
+//! aren't read yet, so a full render still needs the tilesets from a pret checkout.
+
+use crate::map::Layout;
+use crate::palette::Palette;
+use byteorder::{ByteOrder, LittleEndian};
+use std::io;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+/// Address the cartridge ROM is mapped to on the GBA, pointers in the ROM are relative to it.
+const ROM_BASE_ADDRESS: u32 = 0x0800_0000;
+const LAYOUT_HEADER_SIZE: usize = 0x1c;
+const TILESET_HEADER_SIZE: usize = 0x18;
+const PALETTES_PER_TILESET: usize = 16;
+const PALETTE_SIZE: usize = 16 * 2;
+
+/// The `struct MapLayout` header describing a single layout in the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomLayoutHeader {
+    pub width: u32,
+    pub height: u32,
+    pub border_pointer: u32,
+    pub map_pointer: u32,
+    pub primary_tileset_pointer: u32,
+    pub secondary_tileset_pointer: u32,
+    pub border_width: u8,
+    pub border_height: u8,
+}
+
+/// The `struct Tileset` header describing one tileset in the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomTilesetHeader {
+    pub is_compressed: bool,
+    pub is_secondary: bool,
+    pub tiles_pointer: u32,
+    pub palettes_pointer: u32,
+    pub metatiles_pointer: u32,
+    pub metatile_attributes_pointer: u32,
+}
+
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>) -> Self {
+        Rom { data }
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(std::fs::read(path)?))
+    }
+
+    fn bytes_at(&self, offset: usize, len: usize) -> io::Result<&[u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{len} bytes at ROM offset {offset:#x} are past the end of the ROM"),
+                )
+            })
+    }
+
+    fn bytes_at_pointer(&self, pointer: u32, len: usize) -> io::Result<&[u8]> {
+        let offset = pointer.checked_sub(ROM_BASE_ADDRESS).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{pointer:#010x} is not a pointer into the ROM"),
+            )
+        })?;
+        self.bytes_at(offset as usize, len)
+    }
+
+    /// Reads the `index`th entry of a layout table, an array of pointers to layout headers
+    /// such as `gMapLayouts`, which starts at ROM offset `table_offset`.
+    pub fn layout_header_from_table(
+        &self,
+        table_offset: usize,
+        index: usize,
+    ) -> io::Result<RomLayoutHeader> {
+        let offset = index
+            .checked_mul(4)
+            .and_then(|entry_offset| table_offset.checked_add(entry_offset))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("layout table entry {index} at ROM offset {table_offset:#x} overflows"),
+                )
+            })?;
+        let pointer = LittleEndian::read_u32(self.bytes_at(offset, 4)?);
+        let header = self.bytes_at_pointer(pointer, LAYOUT_HEADER_SIZE)?;
+        Ok(Self::parse_layout_header(header))
+    }
+
+    /// Reads the layout header at ROM offset `offset`.
+    pub fn layout_header(&self, offset: usize) -> io::Result<RomLayoutHeader> {
+        let header = self.bytes_at(offset, LAYOUT_HEADER_SIZE)?;
+        Ok(Self::parse_layout_header(header))
+    }
+
+    fn parse_layout_header(header: &[u8]) -> RomLayoutHeader {
+        RomLayoutHeader {
+            width: LittleEndian::read_u32(&header[0x00..]),
+            height: LittleEndian::read_u32(&header[0x04..]),
+            border_pointer: LittleEndian::read_u32(&header[0x08..]),
+            map_pointer: LittleEndian::read_u32(&header[0x0c..]),
+            primary_tileset_pointer: LittleEndian::read_u32(&header[0x10..]),
+            secondary_tileset_pointer: LittleEndian::read_u32(&header[0x14..]),
+            border_width: header[0x18],
+            border_height: header[0x19],
+        }
+    }
+
+    /// Loads the blockdata and border of the layout described by `header`.
+    pub fn load_layout(&self, header: &RomLayoutHeader) -> io::Result<Layout> {
+        let map_len = (header.width as usize)
+            .checked_mul(header.height as usize)
+            .and_then(|cells| cells.checked_mul(2))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "layout of {}x{} metatiles is too large",
+                        header.width, header.height
+                    ),
+                )
+            })?;
+        let border_len = usize::from(header.border_width) * usize::from(header.border_height) * 2;
+        Layout::from_bytes_with_border_size(
+            header.width,
+            header.height,
+            header.border_width.into(),
+            header.border_height.into(),
+            self.bytes_at_pointer(header.map_pointer, map_len)?,
+            self.bytes_at_pointer(header.border_pointer, border_len)?,
+        )
+    }
+
+    pub fn tileset_header(&self, pointer: u32) -> io::Result<RomTilesetHeader> {
+        let header = self.bytes_at_pointer(pointer, TILESET_HEADER_SIZE)?;
+        Ok(RomTilesetHeader {
+            is_compressed: header[0x00] != 0,
+            is_secondary: header[0x01] != 0,
+            tiles_pointer: LittleEndian::read_u32(&header[0x04..]),
+            palettes_pointer: LittleEndian::read_u32(&header[0x08..]),
+            metatiles_pointer: LittleEndian::read_u32(&header[0x0c..]),
+            metatile_attributes_pointer: LittleEndian::read_u32(&header[0x14..]),
+        })
+    }
+
+    /// Loads all 16 palette slots of the tileset described by `header`.
+    pub fn load_palettes(&self, header: &RomTilesetHeader) -> io::Result<Vec<Palette>> {
+        let palettes =
+            self.bytes_at_pointer(header.palettes_pointer, PALETTES_PER_TILESET * PALETTE_SIZE)?;
+        Ok(palettes
+            .chunks_exact(PALETTE_SIZE)
+            .map(|palette| {
+                let mut colors = [0; 16];
+                LittleEndian::read_u16_into(palette, &mut colors);
+                Palette::from_bgr555(colors)
+            })
+            .collect())
+    }
+}
@@ -1,63 +1,362 @@
-use crate::palette::{parse_all_palettes, Palette};
+use crate::dimensions::{
+    metatile_origin, metatile_tile_index, METATILE_DIMENSION, METATILE_LAYERS, METATILE_TILE_GRID,
+    TILES_PER_LAYER, TILES_PER_METATILE, TILE_DIMENSION,
+};
+#[cfg(feature = "fs")]
+use crate::gzip;
+use crate::map::Layout;
+#[cfg(feature = "fs")]
+use crate::palette::lazy_palettes;
+use crate::palette::{LazyPalette, Palette};
+use crate::render::{blit_metatile, image_size};
 use byteorder::{LittleEndian, ReadBytesExt};
-use image::{GrayImage, ImageBuffer, Luma, RgbImage, RgbaImage};
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, Rgba, RgbaImage};
 use png::Decoder;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
 use std::{
+    cell::{OnceCell, RefCell},
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
     io::{self, Read},
-    path::Path,
+    ops::Range,
+    str::FromStr,
 };
 
+/// The game whose tileset conventions should be used when parsing and resolving metatiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameVersion {
+    #[default]
+    FireRedLeafGreen,
+    RubySapphireEmerald,
+}
+
+impl GameVersion {
+    /// Number of metatile ids reserved for the primary tileset (`NUM_METATILES_IN_PRIMARY`),
+    /// secondary metatile ids start immediately after.
+    pub fn primary_metatile_count(self) -> usize {
+        match self {
+            GameVersion::FireRedLeafGreen => 640,
+            GameVersion::RubySapphireEmerald => 512,
+        }
+    }
+
+    /// Number of tile ids reserved for the primary tileset (`NUM_TILES_IN_PRIMARY`),
+    /// secondary tile ids start immediately after.
+    pub fn primary_tile_count(self) -> usize {
+        match self {
+            GameVersion::FireRedLeafGreen => 640,
+            GameVersion::RubySapphireEmerald => 512,
+        }
+    }
+
+    /// Number of palette slots loaded from the primary tileset (`NUM_PALS_IN_PRIMARY`),
+    /// the slots after it are loaded from the secondary tileset.
+    pub fn primary_palette_count(self) -> usize {
+        match self {
+            GameVersion::FireRedLeafGreen => 7,
+            GameVersion::RubySapphireEmerald => 6,
+        }
+    }
+
+    fn attribute_size(self) -> usize {
+        match self {
+            GameVersion::FireRedLeafGreen => 4,
+            GameVersion::RubySapphireEmerald => 2,
+        }
+    }
+}
+
+impl FromStr for GameVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "frlg" | "firered" | "leafgreen" => Ok(GameVersion::FireRedLeafGreen),
+            "rse" | "ruby" | "sapphire" | "emerald" => Ok(GameVersion::RubySapphireEmerald),
+            _ => Err(format!("unknown game version {s}, expected frlg or rse")),
+        }
+    }
+}
+
+/// Which half of a layout's metatile and tile id space a tileset occupies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TilesetRole {
+    Primary,
+    Secondary,
+}
+
+/// A tile reference from a metatile into the id range of the other tileset of a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrossReference {
+    /// Index of the metatile within its own tileset
+    pub metatile_index: usize,
+    /// Position of the tile within the metatile
+    pub tile_index: usize,
+    pub tile_id: u16,
+}
+
+/// What differs between two versions of a tileset, by index within the tileset. Entries
+/// present in only one version count as differing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TilesetDiff {
+    /// Metatiles whose tile references or attributes differ
+    pub metatiles: Vec<usize>,
+    /// Tiles whose palette indices or transparency differ
+    pub tiles: Vec<usize>,
+    /// Palette slots whose colors differ
+    pub palettes: Vec<usize>,
+}
+
+impl TilesetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.metatiles.is_empty() && self.tiles.is_empty() && self.palettes.is_empty()
+    }
+}
+
+/// Why [`LayoutTileset::get_metatile_image`] couldn't draw a metatile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetatileRenderError {
+    /// Neither tileset has the metatile. `max` is one past the highest id the secondary
+    /// tileset covers, though ids between the primary metatiles and the primary metatile
+    /// count are out of range too.
+    OutOfRange { id: u16, max: usize },
+    /// The metatile refers to a tile past the end of its tileset's tile sheet
+    TileDecodeFailed { tile_id: u16 },
+    /// The metatile uses a palette slot which is missing or failed to load
+    PaletteMissing { slot: usize },
+}
+
+impl fmt::Display for MetatileRenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetatileRenderError::OutOfRange { id, max } => {
+                write!(f, "metatile {id} is out of range, max {max}")
+            }
+            MetatileRenderError::TileDecodeFailed { tile_id } => {
+                write!(f, "failed to decode tile {tile_id}")
+            }
+            MetatileRenderError::PaletteMissing { slot } => {
+                write!(f, "no usable palette in slot {slot}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MetatileRenderError {}
+
+impl From<MetatileRenderError> for io::Error {
+    fn from(err: MetatileRenderError) -> Self {
+        let kind = match err {
+            MetatileRenderError::OutOfRange { .. } => io::ErrorKind::NotFound,
+            MetatileRenderError::TileDecodeFailed { .. }
+            | MetatileRenderError::PaletteMissing { .. } => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
 #[derive(Debug)]
 pub struct LayoutTileset {
     primary: Tileset,
     secondary: Tileset,
+    game_version: GameVersion,
+    primary_metatile_count: usize,
+    primary_tile_count: usize,
+    primary_palette_count: usize,
+    forced_palette: Option<usize>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tileset {
     metatiles: Vec<Metatile>,
     tile_image: TilesetImage,
-    palettes: Vec<Palette>,
+    palettes: Vec<LazyPalette>,
+    atlases: Vec<Option<RgbaImage>>,
+    tile_cache: RefCell<HashMap<TileKey, RgbaImage>>,
 }
 
-#[derive(Debug)]
+/// Tilesets compare by their parsed data only, the atlases and tile cache are derived from
+/// it and don't affect equality.
+impl PartialEq for Tileset {
+    fn eq(&self, other: &Self) -> bool {
+        self.metatiles == other.metatiles
+            && self.tile_image == other.tile_image
+            && self.palettes == other.palettes
+    }
+}
+
+impl Eq for Tileset {}
+
+/// Decoded tiles are cached by `(tile_id, palette_number, flip_horizontal, flip_vertical,
+/// own_palettes)`, the last being false when drawn with the other tileset's palettes.
+type TileKey = (usize, usize, bool, bool, bool);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Metatile {
-    tiles: [TileData; 8],
-    _attributes: MetatileAttributes,
+    tiles: [TileData; TILES_PER_METATILE],
+    attributes: MetatileAttributes,
 }
 
-#[derive(Debug)]
+impl Metatile {
+    /// Decodes one FireRed/LeafGreen metatile from its eight little endian tile references
+    /// followed by its 32-bit attributes.
+    pub fn from_bytes(bytes: &[u8; 20]) -> Self {
+        let (tiles, attributes) = bytes.split_at(METATILE_SIZE);
+        let attributes = u32::from_le_bytes(attributes.try_into().unwrap());
+        Self::from_parts(tiles, MetatileAttributes::from(attributes))
+    }
+
+    /// Decodes one Ruby/Sapphire/Emerald metatile from its eight little endian tile
+    /// references followed by its 16-bit attributes.
+    pub fn from_rse_bytes(bytes: &[u8; 18]) -> Self {
+        let (tiles, attributes) = bytes.split_at(METATILE_SIZE);
+        let attributes = u16::from_le_bytes(attributes.try_into().unwrap());
+        Self::from_parts(tiles, MetatileAttributes::from_rse(attributes))
+    }
+
+    /// `tiles` must hold exactly [`METATILE_SIZE`] bytes.
+    fn from_parts(tiles: &[u8], attributes: MetatileAttributes) -> Self {
+        Metatile {
+            tiles: std::array::from_fn(|idx| {
+                TileData::from(u16::from_le_bytes([tiles[2 * idx], tiles[2 * idx + 1]]))
+            }),
+            attributes,
+        }
+    }
+
+    /// The tile references of the bottom layer followed by the top layer, each in row-major
+    /// order.
+    pub fn tiles(&self) -> &[TileData; TILES_PER_METATILE] {
+        &self.tiles
+    }
+
+    pub fn attributes(&self) -> &MetatileAttributes {
+        &self.attributes
+    }
+
+    pub fn layer_type(&self) -> LayerType {
+        self.attributes.layer_type
+    }
+}
+
+/// Size of one metatile's tile references in `metatiles.bin`.
+const METATILE_SIZE: usize = TILES_PER_METATILE * 2;
+
+/// The attribute word stored alongside each metatile. The raw value is kept so fields the
+/// crate doesn't model yet can still be decoded by callers.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MetatileAttributes {
-    _layer_type: LayerType,
+    raw: u32,
+    layer_type: LayerType,
+    behavior: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LayerType {
     MiddleTop,
     BottomMiddle,
     BottomTop,
+    /// A value the games don't define, kept as stored. Drawn like [`LayerType::MiddleTop`].
+    Unknown(u8),
 }
 
-impl From<u32> for MetatileAttributes {
-    fn from(value: u32) -> Self {
-        let value = (value >> 29) & 0b011;
-        let layer_type = if value == 0 {
-            LayerType::MiddleTop
-        } else if value == 1 {
-            LayerType::BottomMiddle
-        } else if value == 2 {
-            LayerType::BottomTop
-        } else {
-            LayerType::MiddleTop
+impl fmt::Display for LayerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LayerType::MiddleTop => "middle-top",
+            LayerType::BottomMiddle => "bottom-middle",
+            LayerType::BottomTop => "bottom-top",
+            LayerType::Unknown(value) => return write!(f, "unknown ({value})"),
         };
+        f.write_str(name)
+    }
+}
+
+/// Parses the names [`LayerType`] displays as, including `unknown (N)`. A bare number is
+/// taken as the raw value stored in the attributes, so `1` is [`LayerType::BottomMiddle`].
+impl FromStr for LayerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "middle-top" => Ok(LayerType::MiddleTop),
+            "bottom-middle" => Ok(LayerType::BottomMiddle),
+            "bottom-top" => Ok(LayerType::BottomTop),
+            _ => {
+                if let Some(value) = s
+                    .strip_prefix("unknown (")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    return value
+                        .parse()
+                        .map(LayerType::Unknown)
+                        .map_err(|err| format!("bad unknown layer type {s}: {err}"));
+                }
+                s.parse()
+                    .map(LayerType::from_bits)
+                    .map_err(|_| format!("unknown layer type {s}"))
+            }
+        }
+    }
+}
 
+impl MetatileAttributes {
+    /// Decodes the 16-bit attributes used by Ruby, Sapphire and Emerald.
+    pub fn from_rse(value: u16) -> Self {
         MetatileAttributes {
-            _layer_type: layer_type,
+            raw: value.into(),
+            layer_type: LayerType::from_bits(((value & 0xf000) >> 12) as u8),
+            behavior: value & 0x00ff,
         }
     }
+
+    /// The attribute word exactly as it was read, zero extended for Ruby/Sapphire/Emerald.
+    pub fn raw(&self) -> u32 {
+        self.raw
+    }
+
+    pub fn layer_type(&self) -> LayerType {
+        self.layer_type
+    }
+
+    /// The metatile behavior, e.g. tall grass or a ledge, as stored in the low bits.
+    pub fn behavior(&self) -> u16 {
+        self.behavior
+    }
 }
 
-#[derive(Debug)]
+impl LayerType {
+    /// Whether the metatile's second group of tiles is drawn on the top background layer,
+    /// covering the player. Covered metatiles draw both groups beneath the player instead.
+    pub fn has_foreground(self) -> bool {
+        match self {
+            LayerType::MiddleTop | LayerType::BottomTop | LayerType::Unknown(_) => true,
+            LayerType::BottomMiddle => false,
+        }
+    }
+
+    fn from_bits(value: u8) -> Self {
+        match value {
+            0 => LayerType::MiddleTop,
+            1 => LayerType::BottomMiddle,
+            2 => LayerType::BottomTop,
+            _ => LayerType::Unknown(value),
+        }
+    }
+}
+
+impl From<u32> for MetatileAttributes {
+    fn from(value: u32) -> Self {
+        MetatileAttributes {
+            raw: value,
+            layer_type: LayerType::from_bits(((value >> 29) & 0b011) as u8),
+            behavior: (value & 0x01ff) as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TileData {
     tile_id: u16,
     flip_horizontal: bool,
@@ -65,116 +364,933 @@ pub struct TileData {
     palette_number: u8,
 }
 
+/// Where a tileset's files are, relative to its directory. The default is pret's layout.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TilesetFiles {
+    pub metatiles: PathBuf,
+    pub attributes: PathBuf,
+    pub tiles: PathBuf,
+    /// A directory of `.pal` files
+    pub palettes: PathBuf,
+}
+
+#[cfg(feature = "fs")]
+impl Default for TilesetFiles {
+    fn default() -> Self {
+        TilesetFiles {
+            metatiles: "metatiles.bin".into(),
+            attributes: "metatile_attributes.bin".into(),
+            tiles: "tiles.png".into(),
+            palettes: "palettes".into(),
+        }
+    }
+}
+
 impl LayoutTileset {
+    /// Loads both tilesets at the same time, one on another thread.
+    #[cfg(feature = "fs")]
     pub fn load_from_paths(
         primary: impl AsRef<Path>,
         secondary: impl AsRef<Path>,
+        game_version: GameVersion,
     ) -> io::Result<LayoutTileset> {
-        let primary = Tileset::load_from_path(primary)?;
-        let secondary = Tileset::load_from_path(secondary)?;
+        let files = TilesetFiles::default();
+        Self::load_with_files(primary, &files, secondary, &files, game_version)
+    }
+
+    /// Like [`LayoutTileset::load_from_paths`], for tileset directories whose files are named
+    /// differently, see [`Tileset::load_with_files`].
+    #[cfg(feature = "fs")]
+    pub fn load_with_files(
+        primary: impl AsRef<Path>,
+        primary_files: &TilesetFiles,
+        secondary: impl AsRef<Path>,
+        secondary_files: &TilesetFiles,
+        game_version: GameVersion,
+    ) -> io::Result<LayoutTileset> {
+        let primary = primary.as_ref();
+        let (primary, secondary) = std::thread::scope(|scope| {
+            let primary =
+                scope.spawn(|| Tileset::load_with_files(primary, primary_files, game_version));
+            let secondary = Tileset::load_with_files(secondary, secondary_files, game_version);
+            let primary = primary
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            (primary, secondary)
+        });
+        Ok(Self::from_tilesets(primary?, secondary?, game_version))
+    }
+
+    pub fn from_tilesets(
+        primary: Tileset,
+        secondary: Tileset,
+        game_version: GameVersion,
+    ) -> LayoutTileset {
         log::info!(
             "Primary metatiles: {}, secondary: {}",
             primary.metatiles.len(),
             secondary.metatiles.len()
         );
 
-        Ok(LayoutTileset { primary, secondary })
+        LayoutTileset {
+            primary,
+            secondary,
+            game_version,
+            primary_metatile_count: game_version.primary_metatile_count(),
+            primary_tile_count: game_version.primary_tile_count(),
+            primary_palette_count: game_version.primary_palette_count(),
+            forced_palette: None,
+        }
+    }
+
+    /// Draws every tile with palette slot `palette` instead of the one its metatile asks
+    /// for. A diagnostic for telling palette selection bugs apart from tile decoding bugs.
+    pub fn with_forced_palette(mut self, palette: Option<usize>) -> Self {
+        self.forced_palette = palette;
+        self
+    }
+
+    /// Overrides where secondary metatile and tile ids start, for projects which changed
+    /// `NUM_METATILES_IN_PRIMARY` or `NUM_TILES_IN_PRIMARY` from the game's defaults.
+    pub fn with_primary_counts(mut self, metatiles: usize, tiles: usize) -> Self {
+        self.primary_metatile_count = metatiles;
+        self.primary_tile_count = tiles;
+        self
     }
 
-    pub fn get_metatile_image(&self, metatile_id: u16) -> Option<RgbImage> {
+    /// Overrides how many palette slots come from the primary tileset, for projects which
+    /// changed `NUM_PALS_IN_PRIMARY`. The remaining slots come from the secondary tileset.
+    pub fn with_primary_palette_count(mut self, palettes: usize) -> Self {
+        self.primary_palette_count = palettes;
+        self
+    }
+
+    pub fn primary_metatile_count(&self) -> usize {
+        self.primary_metatile_count
+    }
+
+    pub fn primary_palette_count(&self) -> usize {
+        self.primary_palette_count
+    }
+
+    pub fn primary_tile_count(&self) -> usize {
+        self.primary_tile_count
+    }
+
+    pub fn primary(&self) -> &Tileset {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &Tileset {
+        &self.secondary
+    }
+
+    pub fn game_version(&self) -> GameVersion {
+        self.game_version
+    }
+
+    /// Returns the ids of every metatile which draws the tile `tile_id`. Both ids use the
+    /// combined primary/secondary numbering found in metatile and map data.
+    pub fn metatiles_using_tile(&self, tile_id: u16) -> Vec<u16> {
+        self.iter_metatiles()
+            .filter(|(_, metatile)| metatile.tiles.iter().any(|tile| tile.tile_id == tile_id))
+            .map(|(metatile_id, _)| metatile_id)
+            .collect()
+    }
+
+    /// Every metatile a layout can refer to paired with its id, primary tileset first. The
+    /// ids match [`LayoutTileset::metatile`], so primary metatiles past the reserved count
+    /// are skipped.
+    pub fn iter_metatiles(&self) -> impl Iterator<Item = (u16, &Metatile)> {
+        let primary = self
+            .primary
+            .metatiles
+            .iter()
+            .take(self.primary_metatile_count)
+            .enumerate();
+        let secondary = self
+            .secondary
+            .metatiles
+            .iter()
+            .enumerate()
+            .map(|(idx, metatile)| (idx + self.primary_metatile_count, metatile));
+        primary
+            .chain(secondary)
+            .map_while(|(metatile_id, metatile)| Some((u16::try_from(metatile_id).ok()?, metatile)))
+    }
+
+    /// Prewarms both tilesets, see [`Tileset::prewarm`]. Each tileset only builds atlases for
+    /// the palette slots it fills, as tiles drawn with the other tileset's palettes never use
+    /// them.
+    pub fn prewarm(&mut self) -> io::Result<()> {
+        let palette_count = self.primary_palette_count;
+        self.primary.prewarm_slots(0..palette_count)?;
+        let secondary_palettes = self.secondary.palettes.len();
+        self.secondary
+            .prewarm_slots(palette_count..secondary_palettes)
+    }
+
+    /// Drops every cached tile image and atlas of both tilesets, see
+    /// [`Tileset::invalidate_cache`].
+    pub fn invalidate_cache(&mut self) {
+        self.primary.invalidate_cache();
+        self.secondary.invalidate_cache();
+    }
+
+    /// Drops the cached images of the tiles `metatile_id` draws, in every palette and flip,
+    /// so they're decoded again next time. Prewarmed atlases are kept, call
+    /// [`LayoutTileset::invalidate_cache`] if the tile sheet or palettes changed.
+    pub fn invalidate_metatile(&mut self, metatile_id: u16) {
+        let Some(metatile) = self.resolve_metatile(metatile_id) else {
+            return;
+        };
+        let tile_ids = metatile
+            .tiles
+            .iter()
+            .map(|tile| usize::from(tile.tile_id))
+            .collect::<Vec<_>>();
+        for tile_id in tile_ids {
+            let (tileset, tile_id) = self.tile_owner(tile_id);
+            tileset
+                .tile_cache
+                .borrow_mut()
+                .retain(|(cached_tile_id, ..), _| *cached_tile_id != tile_id);
+        }
+    }
+
+    /// The metatile a layout refers to by `metatile_id`, from whichever tileset owns it.
+    pub fn metatile(&self, metatile_id: u16) -> Option<&Metatile> {
+        self.resolve_metatile(metatile_id)
+    }
+
+    fn resolve_metatile(&self, metatile_id: u16) -> Option<&Metatile> {
         let metatile_id = metatile_id as usize;
-        let end_of_primary = self.primary.metatiles.len();
-        let end_of_secondary = self.secondary.metatiles.len() + end_of_primary;
-        let metatile = if metatile_id < end_of_primary {
-            log::info!("Used primary metatile id {metatile_id}");
+        let primary_size = self.primary_metatile_count;
+        let end_of_secondary = self.secondary.metatiles.len() + primary_size;
+        if metatile_id < self.primary.metatiles.len().min(primary_size) {
+            log::trace!("Used primary metatile id {metatile_id}");
             Some(self.primary.get_metatile(metatile_id))
-        } else if metatile_id >= end_of_primary && metatile_id < end_of_secondary {
-            Some(self.secondary.get_metatile(metatile_id - 640))
+        } else if metatile_id >= primary_size && metatile_id < end_of_secondary {
+            Some(self.secondary.get_metatile(metatile_id - primary_size))
         } else {
             None
+        }
+    }
+
+    /// Returns the `(metatile_id, tile_id)` of every tile reference which points past the
+    /// end of the tile sheet owning that id, in metatile order. Such tiles can't be drawn.
+    pub fn validate_tiles(&self) -> Vec<(u16, u16)> {
+        self.iter_metatiles()
+            .flat_map(|(metatile_id, metatile)| {
+                metatile
+                    .tiles
+                    .iter()
+                    .map(move |tile| (metatile_id, tile.tile_id))
+            })
+            .filter(|(_, tile_id)| {
+                let (tileset, tile_id) = self.tile_owner(usize::from(*tile_id));
+                tile_id >= tileset.tile_count()
+            })
+            .collect()
+    }
+
+    /// Tiles drawn in a metatile's bottom layer which look like they use palette index 0 as a
+    /// real color rather than for transparency: most, but not all, of their pixels are index
+    /// 0. Blank tiles aren't included. Returns sorted tile ids as metatiles refer to them. If
+    /// many tiles show up here, drawing index 0 as transparent is likely to punch holes in
+    /// the floor.
+    pub fn index_zero_usage(&self) -> Vec<u16> {
+        let bottom_tiles = self
+            .iter_metatiles()
+            .flat_map(|(_, metatile)| &metatile.tiles[..TILES_PER_LAYER])
+            .map(|tile| tile.tile_id)
+            .collect::<BTreeSet<_>>();
+        bottom_tiles
+            .into_iter()
+            .filter(|tile_id| {
+                let (tileset, tile_id) = self.tile_owner(usize::from(*tile_id));
+                tileset.mostly_index_zero(tile_id)
+            })
+            .collect()
+    }
+
+    /// Returns the `(row, col, metatile_id)` of every cell in `layout` whose metatile id
+    /// doesn't exist in either tileset.
+    pub fn validate_layout(&self, layout: &Layout) -> Vec<(u32, u32, u16)> {
+        let mut invalid = vec![];
+        for row in 0..layout.height() {
+            for col in 0..layout.width() {
+                if let Some(metatile_data) = layout.get_metatile(row, col) {
+                    if self.resolve_metatile(metatile_data.metatile_id()).is_none() {
+                        invalid.push((row, col, metatile_data.metatile_id()));
+                    }
+                }
+            }
+        }
+        invalid
+    }
+
+    /// Lays out `metatile_ids` left to right, top to bottom in rows of `width` metatiles,
+    /// independent of any layout. Ids which can't be drawn are left black. Fails if the image
+    /// would be too large, see [`image_size`].
+    pub fn render_grid(&self, metatile_ids: &[u16], width: u32) -> io::Result<RgbImage> {
+        if width == 0 {
+            return Ok(RgbImage::new(0, 0));
+        }
+        let height = u32::try_from(metatile_ids.len().div_ceil(width as usize))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many metatile rows"))?;
+        image_size(width, height, 1)?;
+        let mut image = RgbImage::new(METATILE_DIMENSION * width, METATILE_DIMENSION * height);
+        for (idx, metatile_id) in metatile_ids.iter().enumerate() {
+            let row = idx as u32 / width;
+            let col = idx as u32 % width;
+            let (x, y) = metatile_origin(row, col);
+            match self.get_metatile_image(*metatile_id) {
+                Ok(metatile_image) => blit_metatile(&mut image, &metatile_image, x, y),
+                Err(err) => log::error!("Failed to get metatile image for id {metatile_id}: {err}"),
+            }
+        }
+        Ok(image)
+    }
+
+    /// Whether any pixel of the metatile's top layer is transparent, letting whatever is
+    /// beneath the metatile show through. `None` if the metatile or its tiles can't be found.
+    pub fn metatile_has_transparency(&self, metatile_id: u16) -> Option<bool> {
+        const ALPHA: usize = 3;
+        let metatile = self.resolve_metatile(metatile_id)?;
+        let top_layer = &metatile.tiles[TILES_PER_LAYER..];
+        for tile in top_layer {
+            let tile_image = self.tile_image(tile)?;
+            if tile_image.pixels().any(|pixel| pixel.0[ALPHA] == 0) {
+                return Some(true);
+            }
+        }
+        Some(false)
+    }
+
+    /// Checks that `metatile_id` exists and that every tile it draws is in its tile sheet
+    /// and uses a palette which loads, describing the first problem found.
+    pub fn check_metatile(&self, metatile_id: u16) -> io::Result<()> {
+        let Some(metatile) = self.resolve_metatile(metatile_id) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no metatile with id {metatile_id}"),
+            ));
         };
+        for tile in &metatile.tiles {
+            let tile_id = usize::from(tile.tile_id);
+            let (tileset, tileset_tile_id) = self.tile_owner(tile_id);
+            let palette_slot = self.palette_slot(tile);
+            tileset
+                .check_tile(tileset_tile_id)
+                .and_then(|()| match tileset.tile_image.derived_palette {
+                    Some(_) => Ok(()),
+                    None => self
+                        .palette_tileset(palette_slot)
+                        .check_palette(palette_slot),
+                })
+                .map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!("metatile {metatile_id} tile {tile_id}: {err}"),
+                    )
+                })?;
+        }
+        Ok(())
+    }
 
-        if let Some(metatile) = metatile {
-            let mut metatile_image: RgbImage = ImageBuffer::new(16, 16);
-
-            for layer in 0..2 {
-                for col in 0..2 {
-                    for row in 0..2 {
-                        let top_layer = layer == 1;
-                        let tile_idx = (layer * 4 + row * 2 + col) as usize;
-
-                        let tileset_tile_id = metatile.tiles[tile_idx].tile_id;
-                        let tile_image = if tileset_tile_id < 640 {
-                            self.primary.get_tile_image(
-                                metatile.tiles[tile_idx].tile_id.into(),
-                                metatile.tiles[tile_idx].flip_vertical,
-                                metatile.tiles[tile_idx].flip_horizontal,
-                                metatile.tiles[tile_idx].palette_number.into(),
-                                &self.primary.tile_image,
-                            )
-                        } else {
-                            self.secondary.get_tile_image(
-                                (metatile.tiles[tile_idx].tile_id - 640).into(),
-                                metatile.tiles[tile_idx].flip_vertical,
-                                metatile.tiles[tile_idx].flip_horizontal,
-                                metatile.tiles[tile_idx].palette_number.into(),
-                                &self.secondary.tile_image,
-                            )
-                        };
-
-                        if let Some(tile_image) = tile_image {
-                            for pixel_row in 0..8 {
-                                for pixel_col in 0..8 {
-                                    let output_row = 8 * row + pixel_row;
-                                    let output_col = 8 * col + pixel_col;
-                                    const ALPHA: usize = 3;
-                                    if top_layer
-                                        && tile_image.get_pixel(pixel_col, pixel_row).0[ALPHA] == 0
-                                    {
-                                        continue;
-                                    }
-                                    metatile_image
-                                        .get_pixel_mut(output_col, output_row)
-                                        .0
-                                        .copy_from_slice(
-                                            &tile_image.get_pixel(pixel_col, pixel_row).0[..=2],
-                                        );
-                                }
-                            }
-                        } else {
-                            log::error!("Failed to get tile image for tile id {tileset_tile_id}");
+    /// The palette color index of each pixel of the metatile as drawn, i.e. the top layer's
+    /// index wherever it isn't transparent and the bottom layer's everywhere else.
+    pub fn metatile_color_indices(
+        &self,
+        metatile_id: u16,
+    ) -> Result<GrayImage, MetatileRenderError> {
+        let metatile = self
+            .resolve_metatile(metatile_id)
+            .ok_or_else(|| self.out_of_range(metatile_id))?;
+        let mut indices = GrayImage::new(METATILE_DIMENSION, METATILE_DIMENSION);
+        for layer in 0..METATILE_LAYERS {
+            for row in 0..METATILE_TILE_GRID {
+                for col in 0..METATILE_TILE_GRID {
+                    let tile = &metatile.tiles[metatile_tile_index(layer, row, col)];
+                    let (tileset, tile_id) = self.tile_owner(usize::from(tile.tile_id));
+                    let tile_indices = tileset.tile_image.get_tile(tile_id).ok_or(
+                        MetatileRenderError::TileDecodeFailed {
+                            tile_id: tile.tile_id,
+                        },
+                    )?;
+                    let tile_indices = match (tile.flip_horizontal, tile.flip_vertical) {
+                        (false, false) => tile_indices.clone(),
+                        (true, false) => image::imageops::flip_horizontal(tile_indices),
+                        (false, true) => image::imageops::flip_vertical(tile_indices),
+                        (true, true) => image::imageops::rotate180(tile_indices),
+                    };
+                    for (x, y, index) in tile_indices.enumerate_pixels() {
+                        if layer > 0 && tileset.tile_image.is_transparent(index.0[0]) {
+                            continue;
                         }
+                        indices.put_pixel(
+                            col * TILE_DIMENSION + x,
+                            row * TILE_DIMENSION + y,
+                            *index,
+                        );
                     }
                 }
             }
+        }
+        Ok(indices)
+    }
+
+    fn out_of_range(&self, metatile_id: u16) -> MetatileRenderError {
+        MetatileRenderError::OutOfRange {
+            id: metatile_id,
+            max: self.primary_metatile_count + self.secondary.metatiles.len(),
+        }
+    }
 
-            Some(metatile_image)
+    /// Works out why [`LayoutTileset::tile_image`] couldn't draw `tile`.
+    fn tile_error(&self, tile: &TileData) -> MetatileRenderError {
+        let (tileset, tile_id) = self.tile_owner(usize::from(tile.tile_id));
+        if tileset.check_tile(tile_id).is_err() {
+            MetatileRenderError::TileDecodeFailed {
+                tile_id: tile.tile_id,
+            }
         } else {
-            None
+            MetatileRenderError::PaletteMissing {
+                slot: self.palette_slot(tile),
+            }
+        }
+    }
+
+    /// Decodes a tile a metatile refers to from whichever tileset owns its tile id, flipped
+    /// and colored as the metatile draws it.
+    pub fn tile_image(&self, tile: &TileData) -> Option<RgbaImage> {
+        let (tileset, tile_id) = self.tile_owner(usize::from(tile.tile_id));
+        let palette_slot = self.palette_slot(tile);
+        tileset.get_tile_image(
+            tile_id,
+            tile.flip_vertical,
+            tile.flip_horizontal,
+            palette_slot,
+            self.palette_tileset(palette_slot),
+        )
+    }
+
+    /// The tileset whose tile sheet holds `tile_id`, and the tile's index within that sheet.
+    fn tile_owner(&self, tile_id: usize) -> (&Tileset, usize) {
+        if tile_id < self.primary_tile_count {
+            (&self.primary, tile_id)
+        } else {
+            (&self.secondary, tile_id - self.primary_tile_count)
+        }
+    }
+
+    fn palette_slot(&self, tile: &TileData) -> usize {
+        self.forced_palette
+            .unwrap_or_else(|| tile.palette_number.into())
+    }
+
+    /// The tileset whose palettes fill `slot`, regardless of which tileset a tile is from.
+    fn palette_tileset(&self, slot: usize) -> &Tileset {
+        if slot < self.primary_palette_count {
+            &self.primary
+        } else {
+            &self.secondary
         }
     }
+
+    /// Draws the metatile a layout refers to by `metatile_id`, failing on the first tile
+    /// which can't be drawn. This is [`LayoutTileset::get_metatile_image_rgba`] with the
+    /// alpha channel dropped.
+    pub fn get_metatile_image(&self, metatile_id: u16) -> Result<RgbImage, MetatileRenderError> {
+        self.draw_metatile(metatile_id, true)
+            .map(|image| drop_alpha(&image))
+    }
+
+    /// Draws the metatile a layout refers to by `metatile_id`, keeping transparency: a pixel
+    /// is transparent where no layer draws an opaque pixel. Transparent pixels still carry
+    /// the bottom layer's color, which is what [`LayoutTileset::get_metatile_image`] shows.
+    pub fn get_metatile_image_rgba(
+        &self,
+        metatile_id: u16,
+    ) -> Result<RgbaImage, MetatileRenderError> {
+        self.draw_metatile(metatile_id, true)
+    }
+
+    /// Like [`LayoutTileset::get_metatile_image`], but leaves out any tiles drawn above the
+    /// player such as treetops and roofs, revealing the floor beneath them.
+    pub fn get_metatile_background_image(
+        &self,
+        metatile_id: u16,
+    ) -> Result<RgbImage, MetatileRenderError> {
+        self.draw_metatile(metatile_id, false)
+            .map(|image| drop_alpha(&image))
+    }
+
+    /// Like [`LayoutTileset::get_metatile_background_image`], keeping transparency as
+    /// [`LayoutTileset::get_metatile_image_rgba`] does.
+    pub fn get_metatile_background_image_rgba(
+        &self,
+        metatile_id: u16,
+    ) -> Result<RgbaImage, MetatileRenderError> {
+        self.draw_metatile(metatile_id, false)
+    }
+
+    /// Fast path for [`LayoutTileset::draw_metatile`] when the first `layers` layers of
+    /// `metatile` all use one palette slot. Each tile row is mapped through a single 16 entry
+    /// RGBA table per tile sheet rather than going through the per tile caches. Returns
+    /// `None` for mixed palettes, and for missing tiles or palettes so the general path can
+    /// report them.
+    fn draw_uniform_palette(&self, metatile: &Metatile, layers: usize) -> Option<RgbaImage> {
+        let tiles = &metatile.tiles[..layers * TILES_PER_LAYER];
+        let slot = self.palette_slot(&tiles[0]);
+        if tiles.iter().any(|tile| self.palette_slot(tile) != slot) {
+            return None;
+        }
+        let slot_palette = self
+            .palette_tileset(slot)
+            .palettes
+            .get(slot)
+            .and_then(|palette| palette.load().ok());
+        // Transparency and converted sheets' colors come from each tile sheet, so primary and
+        // secondary tiles get their own table.
+        let mut tables = [[Rgba([0; 4]); 16]; 2];
+        for (table, tileset) in tables.iter_mut().zip([&self.primary, &self.secondary]) {
+            let palette = tileset
+                .tile_image
+                .derived_palette
+                .as_ref()
+                .or(slot_palette)?;
+            *table = std::array::from_fn(|index| {
+                let (r, g, b) = *palette.get(index);
+                let alpha = if tileset.tile_image.is_transparent(index as u8) {
+                    0
+                } else {
+                    255
+                };
+                Rgba([r, g, b, alpha])
+            });
+        }
+
+        const TILE_WIDTH: usize = TILE_DIMENSION as usize;
+        let mut metatile_image = RgbaImage::new(METATILE_DIMENSION, METATILE_DIMENSION);
+        for (tile_idx, tile) in tiles.iter().enumerate() {
+            let top_layer = tile_idx >= TILES_PER_LAYER;
+            let position = (tile_idx % TILES_PER_LAYER) as u32;
+            let origin_x = TILE_DIMENSION * (position % METATILE_TILE_GRID);
+            let origin_y = TILE_DIMENSION * (position / METATILE_TILE_GRID);
+            let (tileset, tile_id) = self.tile_owner(usize::from(tile.tile_id));
+            if tile_id >= tileset.tile_image.tile_count() {
+                return None;
+            }
+            let table = &tables[usize::from(!std::ptr::eq(tileset, &self.primary))];
+            let indices = tileset.tile_image.get_tile(tile_id)?;
+            for (y, source_row) in indices.as_raw().chunks_exact(TILE_WIDTH).enumerate() {
+                let y = if tile.flip_vertical {
+                    TILE_WIDTH - 1 - y
+                } else {
+                    y
+                };
+                let mut row: [Rgba<u8>; TILE_WIDTH] =
+                    std::array::from_fn(|x| table[usize::from(source_row[x])]);
+                if tile.flip_horizontal {
+                    row.reverse();
+                }
+                let start =
+                    ((origin_y as usize + y) * METATILE_DIMENSION as usize + origin_x as usize) * 4;
+                let destination = &mut metatile_image.as_mut()[start..start + TILE_WIDTH * 4];
+                for (pixel, color) in destination.chunks_exact_mut(4).zip(row) {
+                    if !top_layer || color.0[3] != 0 {
+                        pixel.copy_from_slice(&color.0);
+                    }
+                }
+            }
+        }
+        Some(metatile_image)
+    }
+
+    fn draw_metatile(
+        &self,
+        metatile_id: u16,
+        include_foreground: bool,
+    ) -> Result<RgbaImage, MetatileRenderError> {
+        let metatile = self
+            .resolve_metatile(metatile_id)
+            .ok_or_else(|| self.out_of_range(metatile_id))?;
+        let layers = if include_foreground || !metatile.attributes.layer_type.has_foreground() {
+            METATILE_LAYERS
+        } else {
+            1
+        };
+
+        if let Some(metatile_image) = self.draw_uniform_palette(metatile, layers) {
+            return Ok(metatile_image);
+        }
+
+        let mut metatile_image: RgbaImage =
+            ImageBuffer::new(METATILE_DIMENSION, METATILE_DIMENSION);
+
+        for layer in 0..layers {
+            for col in 0..METATILE_TILE_GRID {
+                for row in 0..METATILE_TILE_GRID {
+                    let top_layer = layer == 1;
+                    let tile = &metatile.tiles[metatile_tile_index(layer, row, col)];
+                    let tile_image = self.tile_image(tile).ok_or_else(|| self.tile_error(tile))?;
+
+                    for pixel_row in 0..TILE_DIMENSION {
+                        for pixel_col in 0..TILE_DIMENSION {
+                            let output_row = TILE_DIMENSION * row + pixel_row;
+                            let output_col = TILE_DIMENSION * col + pixel_col;
+                            const ALPHA: usize = 3;
+                            if top_layer && tile_image.get_pixel(pixel_col, pixel_row).0[ALPHA] == 0
+                            {
+                                continue;
+                            }
+                            metatile_image.put_pixel(
+                                output_col,
+                                output_row,
+                                *tile_image.get_pixel(pixel_col, pixel_row),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(metatile_image)
+    }
+}
+
+/// Keeps the color of every pixel, including transparent ones, without blending.
+fn drop_alpha(image: &RgbaImage) -> RgbImage {
+    ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        Rgb([r, g, b])
+    })
 }
 
 impl Tileset {
-    fn load_from_path(path: impl AsRef<Path>) -> io::Result<Self> {
-        let mut metatile_file = path.as_ref().to_path_buf();
-        metatile_file.push("metatiles.bin");
-        let mut metatile_attrs_file = path.as_ref().to_path_buf();
-        metatile_attrs_file.push("metatile_attributes.bin");
-        let metatiles = parse_metatile_files(metatile_file, metatile_attrs_file)?;
-
-        let mut tileset_png_file = path.as_ref().to_path_buf();
-        tileset_png_file.push("tiles.png");
-        let tile_image = parse_tileset_png(tileset_png_file)?;
-
-        let mut palettes_dir = path.as_ref().to_path_buf();
-        palettes_dir.push("palettes");
-        let palettes = parse_all_palettes(palettes_dir)?;
-
-        Ok(Tileset {
+    /// Loads a tileset directory from a pret checkout, e.g. `data/tilesets/primary/general`.
+    /// A missing `metatile_attributes.bin` is tolerated, every metatile is then middle-top.
+    #[cfg(feature = "fs")]
+    pub fn load_from_path(path: impl AsRef<Path>, game_version: GameVersion) -> io::Result<Self> {
+        Self::load_with_files(path, &TilesetFiles::default(), game_version)
+    }
+
+    /// Like [`Tileset::load_from_path`], for a tileset directory whose files are named
+    /// differently.
+    #[cfg(feature = "fs")]
+    pub fn load_with_files(
+        path: impl AsRef<Path>,
+        files: &TilesetFiles,
+        game_version: GameVersion,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metatiles = parse_metatile_files(
+            path.join(&files.metatiles),
+            path.join(&files.attributes),
+            game_version,
+        )?;
+        let tile_image = parse_tileset_png(gzip::open(path.join(&files.tiles))?)?;
+        let palettes = lazy_palettes(path.join(&files.palettes))?;
+
+        Ok(Self::from_parts(metatiles, tile_image, palettes))
+    }
+
+    /// Swaps this tileset's palettes for the `.pal` files in `palettes_dir`, keeping its
+    /// tiles and metatiles. Useful for trying out recolors without touching the originals.
+    #[cfg(feature = "fs")]
+    pub fn with_palettes_dir(self, palettes_dir: impl AsRef<Path>) -> io::Result<Self> {
+        let palettes = lazy_palettes(palettes_dir)?;
+        Ok(Self::from_parts(self.metatiles, self.tile_image, palettes))
+    }
+
+    /// Builds a tileset from the raw contents of its `metatiles.bin`,
+    /// `metatile_attributes.bin` and `tiles.png` files, plus its already parsed palettes.
+    pub fn from_bytes(
+        metatiles: &[u8],
+        attributes: &[u8],
+        tiles_png: &[u8],
+        palettes: Vec<Palette>,
+        game_version: GameVersion,
+    ) -> io::Result<Self> {
+        let metatiles = parse_metatile_data(metatiles, attributes, game_version)?;
+        let tile_image = parse_tileset_png(tiles_png)?;
+        let palettes = palettes.into_iter().map(LazyPalette::loaded).collect();
+        Ok(Self::from_parts(metatiles, tile_image, palettes))
+    }
+
+    fn from_parts(
+        metatiles: Vec<Metatile>,
+        tile_image: TilesetImage,
+        palettes: Vec<LazyPalette>,
+    ) -> Self {
+        Tileset {
             metatiles,
             tile_image,
             palettes,
+            atlases: Vec::new(),
+            tile_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Decodes the whole tile sheet once per palette so that tile lookups become plain 8x8
+    /// copies out of an RGBA atlas. Trades memory for render speed.
+    pub fn prewarm(&mut self) -> io::Result<()> {
+        self.prewarm_slots(0..self.palettes.len())
+    }
+
+    /// Like [`Tileset::prewarm`], but only builds atlases for the palette slots in `slots`.
+    /// Slots past the end of the tileset's palettes are skipped.
+    pub fn prewarm_slots(&mut self, slots: Range<usize>) -> io::Result<()> {
+        let slots = slots.start..slots.end.min(self.palettes.len());
+        if self.atlases.len() < slots.end {
+            self.atlases.resize(slots.end, None);
+        }
+        for slot in slots {
+            let palette = self.palettes[slot].load()?;
+            let palette = self.tile_image.derived_palette.as_ref().unwrap_or(palette);
+            self.atlases[slot] = Some(self.tile_image.to_rgba(palette));
+        }
+        Ok(())
+    }
+
+    /// Drops every cached tile image and any atlases built by [`Tileset::prewarm`]. Images are
+    /// cached per tile, palette and flip, so call this after anything which changes a tile's
+    /// pixels or a palette's colors. Editing which tiles a metatile uses needs no
+    /// invalidation, as whole metatiles aren't cached.
+    pub fn invalidate_cache(&mut self) {
+        self.atlases.clear();
+        self.tile_cache.get_mut().clear();
+    }
+
+    /// Palettes are otherwise only parsed when a tile first uses them, this parses every
+    /// palette up front instead.
+    pub fn prewarm_palettes(&self) -> io::Result<()> {
+        for palette in &self.palettes {
+            palette.load()?;
+        }
+        Ok(())
+    }
+
+    fn check_tile(&self, tile_id: usize) -> io::Result<()> {
+        if tile_id >= self.tile_image.tile_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "tile {tile_id} is out of range, the tile sheet has {}",
+                    self.tile_image.tile_count()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_palette(&self, palette_number: usize) -> io::Result<()> {
+        let Some(palette) = self.palettes.get(palette_number) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "no palette in slot {palette_number}, only {} loaded",
+                    self.palettes.len()
+                ),
+            ));
+        };
+        palette.load().map(|_| ())
+    }
+
+    fn palette(&self, slot: usize) -> Option<&Palette> {
+        let Some(palette) = self.palettes.get(slot) else {
+            log::error!(
+                "No palette in slot {slot}, only {} loaded",
+                self.palettes.len()
+            );
+            return None;
+        };
+        match palette.load() {
+            Ok(palette) => Some(palette),
+            Err(err) => {
+                log::error!("Failed to load palette {slot}: {err}");
+                None
+            }
+        }
+    }
+
+    pub fn metatile_count(&self) -> usize {
+        self.metatiles.len()
+    }
+
+    /// The highest tile id any of this tileset's metatiles use, or `None` without metatiles.
+    /// Ids are in the layout's tile id space, so may point into either tileset.
+    pub fn max_referenced_tile_id(&self) -> Option<u16> {
+        self.metatiles
+            .iter()
+            .flat_map(|metatile| metatile.tiles.iter().map(|tile| tile.tile_id))
+            .max()
+    }
+
+    /// The metatile at `index` within this tileset, which for a secondary tileset is its id
+    /// minus the primary metatile count. See [`LayoutTileset::metatile`] for layout ids.
+    pub fn metatile(&self, index: usize) -> Option<&Metatile> {
+        self.metatiles.get(index)
+    }
+
+    pub fn palette_count(&self) -> usize {
+        self.palettes.len()
+    }
+
+    /// Number of 8x8 tiles in the tile sheet.
+    pub fn tile_count(&self) -> usize {
+        self.tile_image.tile_count()
+    }
+
+    /// `(width, height)` of the tile sheet, in pixels.
+    pub fn tile_sheet_size(&self) -> (u32, u32) {
+        (
+            self.tile_image.tiles_wide() as u32 * TILE_DIMENSION,
+            self.tile_image.tiles_high() as u32 * TILE_DIMENSION,
+        )
+    }
+
+    /// The decoded tile sheet.
+    pub fn tile_sheet(&self) -> &TilesetImage {
+        &self.tile_image
+    }
+
+    /// The colors found in a tile sheet which had to be converted because it wasn't saved as
+    /// a 4bpp indexed png, which its tiles are drawn with in place of the palettes. `None`
+    /// for a normal sheet.
+    pub fn derived_palette(&self) -> Option<&Palette> {
+        self.tile_image.derived_palette.as_ref()
+    }
+
+    /// Returns the slots of palettes whose colors are all black, which usually means the
+    /// palette file was malformed. Loads any palettes which haven't been yet.
+    pub fn blank_palettes(&self) -> Vec<usize> {
+        self.palettes
+            .iter()
+            .enumerate()
+            .filter(|(_, palette)| palette.load().is_ok_and(Palette::is_blank))
+            .map(|(slot, _)| slot)
+            .collect()
+    }
+
+    /// Returns the slots of loaded palettes which no tile of any metatile refers to.
+    pub fn unused_palettes(&self) -> Vec<usize> {
+        let used = self
+            .metatiles
+            .iter()
+            .flat_map(|metatile| metatile.tiles.iter())
+            .map(|tile| usize::from(tile.palette_number))
+            .collect::<HashSet<_>>();
+        (0..self.palettes.len())
+            .filter(|slot| !used.contains(slot))
+            .collect()
+    }
+
+    /// Returns the indices of tiles in this tileset's tile sheet which none of its own
+    /// metatiles draw. Metatile tile ids are translated to sheet indices using the role this
+    /// tileset plays and the number of tile ids reserved for the primary tileset. Note that
+    /// a primary tile may still be used by secondary tilesets' metatiles.
+    pub fn unused_tiles(&self, role: TilesetRole, primary_tile_count: usize) -> Vec<usize> {
+        let used = self
+            .metatiles
+            .iter()
+            .flat_map(|metatile| metatile.tiles.iter())
+            .filter_map(|tile| {
+                let tile_id = usize::from(tile.tile_id);
+                match role {
+                    TilesetRole::Primary => (tile_id < primary_tile_count).then_some(tile_id),
+                    TilesetRole::Secondary => tile_id.checked_sub(primary_tile_count),
+                }
+            })
+            .collect::<HashSet<_>>();
+        (0..self.tile_image.tile_count())
+            .filter(|tile| !used.contains(tile))
+            .collect()
+    }
+
+    /// Lists every tile reference from this tileset's metatiles which falls in the tile id
+    /// range of the other tileset in a layout, given the role this tileset plays and the
+    /// number of tile ids reserved for the primary tileset.
+    pub fn cross_reference_report(
+        &self,
+        role: TilesetRole,
+        primary_tile_count: usize,
+    ) -> Vec<CrossReference> {
+        self.metatiles
+            .iter()
+            .enumerate()
+            .flat_map(|(metatile_index, metatile)| {
+                metatile
+                    .tiles
+                    .iter()
+                    .enumerate()
+                    .map(move |(tile_index, tile)| CrossReference {
+                        metatile_index,
+                        tile_index,
+                        tile_id: tile.tile_id,
+                    })
+            })
+            .filter(|reference| {
+                let in_primary = usize::from(reference.tile_id) < primary_tile_count;
+                match role {
+                    TilesetRole::Primary => !in_primary,
+                    TilesetRole::Secondary => in_primary,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether most, but not all, of the tile's pixels are palette index 0.
+    fn mostly_index_zero(&self, tile_id: usize) -> bool {
+        const PIXELS_PER_TILE: usize = (TILE_DIMENSION * TILE_DIMENSION) as usize;
+        if tile_id >= self.tile_count() {
+            return false;
+        }
+        let Some(indices) = self.tile_image.get_tile(tile_id) else {
+            return false;
+        };
+        let zeros = indices.pixels().filter(|index| index.0[0] == 0).count();
+        zeros * 2 > PIXELS_PER_TILE && zeros < PIXELS_PER_TILE
+    }
+
+    /// Compares this tileset against `other` by what they draw rather than by their files,
+    /// e.g. to check that a refactored tileset still renders the same. Loads any palettes
+    /// which haven't been yet.
+    pub fn semantic_diff(&self, other: &Tileset) -> io::Result<TilesetDiff> {
+        let metatiles = differing_indices(&self.metatiles, &other.metatiles);
+
+        let tile_count = self.tile_count().max(other.tile_count());
+        let tiles = (0..tile_count)
+            .filter(|&tile_id| {
+                let pixels = |tileset: &Tileset| {
+                    if tile_id >= tileset.tile_count() {
+                        return None;
+                    }
+                    let indices = tileset.tile_image.get_tile(tile_id)?;
+                    let transparency = indices
+                        .pixels()
+                        .map(|index| tileset.tile_image.is_transparent(index.0[0]));
+                    Some((indices.clone(), transparency.collect::<Vec<_>>()))
+                };
+                pixels(self) != pixels(other)
+            })
+            .collect();
+
+        let load = |palettes: &[LazyPalette]| {
+            palettes
+                .iter()
+                .map(|palette| palette.load().cloned())
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let palettes = differing_indices(&load(&self.palettes)?, &load(&other.palettes)?);
+
+        Ok(TilesetDiff {
+            metatiles,
+            tiles,
+            palettes,
         })
     }
 
@@ -182,25 +1298,98 @@ impl Tileset {
         &self.metatiles[metatile_id]
     }
 
+    /// Draws one of this tileset's tiles with palette `palette_number` of `palettes`, which
+    /// is either this tileset or the other one in its layout.
     fn get_tile_image(
         &self,
         tile_id: usize,
         flip_vertical: bool,
         flip_horizontal: bool,
         palette_number: usize,
-        tileset_image: &TilesetImage,
+        palettes: &Tileset,
     ) -> Option<RgbaImage> {
+        let own_palettes = std::ptr::eq(self, palettes);
+        let key = (
+            tile_id,
+            palette_number,
+            flip_horizontal,
+            flip_vertical,
+            own_palettes,
+        );
+        if let Some(tile_image) = self.tile_cache.borrow().get(&key) {
+            return Some(tile_image.clone());
+        }
+
+        let tile_image = self.decode_tile_image(
+            tile_id,
+            flip_vertical,
+            flip_horizontal,
+            palette_number,
+            palettes,
+        )?;
+        self.tile_cache.borrow_mut().insert(key, tile_image.clone());
+        Some(tile_image)
+    }
+
+    fn decode_tile_image(
+        &self,
+        tile_id: usize,
+        flip_vertical: bool,
+        flip_horizontal: bool,
+        palette_number: usize,
+        palettes: &Tileset,
+    ) -> Option<RgbaImage> {
+        let tileset_image = &self.tile_image;
+        let source_pixel = |row: u32, col: u32| {
+            let tile_row = if !flip_vertical {
+                row
+            } else {
+                TILE_DIMENSION - 1 - row
+            };
+            let tile_col = if !flip_horizontal {
+                col
+            } else {
+                TILE_DIMENSION - 1 - col
+            };
+            (tile_row, tile_col)
+        };
+
+        // Atlases are this tileset's sheet drawn with its own palettes
+        if let Some(atlas) = self
+            .atlases
+            .get(palette_number)
+            .and_then(Option::as_ref)
+            .filter(|_| std::ptr::eq(self, palettes))
+        {
+            let (tile_x, tile_y) = tileset_image.tile_pixel_origin(tile_id)?;
+            return Some(ImageBuffer::from_fn(
+                TILE_DIMENSION,
+                TILE_DIMENSION,
+                |col, row| {
+                    let (tile_row, tile_col) = source_pixel(row, col);
+                    *atlas.get_pixel(tile_x + tile_col, tile_y + tile_row)
+                },
+            ));
+        }
+
+        // Converted sheets are drawn in their own colors, whatever the palette slot
+        let palette = match &tileset_image.derived_palette {
+            Some(derived) => derived,
+            None => palettes.palette(palette_number)?,
+        };
         let gray_tile = tileset_image.get_tile(tile_id)?;
-        let mut tile_image: RgbaImage = ImageBuffer::new(8, 8);
-        for row in 0..8 {
-            for col in 0..8 {
-                let tile_row = if !flip_vertical { row } else { 7 - row };
-                let tile_col = if !flip_horizontal { col } else { 7 - col };
-
-                let palette_value = self.palettes[palette_number]
-                    .get(gray_tile.get_pixel(tile_col, tile_row).0[0] as usize);
-                let transparent = gray_tile.get_pixel(tile_col, tile_row).0[0] == 0;
-                let alpha = if transparent { 0 } else { 255 };
+        let mut tile_image: RgbaImage = ImageBuffer::new(TILE_DIMENSION, TILE_DIMENSION);
+        for row in 0..TILE_DIMENSION {
+            for col in 0..TILE_DIMENSION {
+                let (tile_row, tile_col) = source_pixel(row, col);
+
+                let index = gray_tile.get_pixel(tile_col, tile_row).0[0];
+                let palette_value = palette.get(index as usize);
+                let alpha = if tileset_image.is_transparent(index) {
+                    0
+                } else {
+                    255
+                };
                 tile_image.get_pixel_mut(col, row).0 =
                     [palette_value.0, palette_value.1, palette_value.2, alpha];
             }
@@ -209,6 +1398,33 @@ impl Tileset {
     }
 }
 
+impl TileData {
+    pub fn tile_id(&self) -> u16 {
+        self.tile_id
+    }
+
+    pub fn palette_number(&self) -> u8 {
+        self.palette_number
+    }
+
+    pub fn flip_horizontal(&self) -> bool {
+        self.flip_horizontal
+    }
+
+    pub fn flip_vertical(&self) -> bool {
+        self.flip_vertical
+    }
+
+    /// The tile reference packed as it's stored in `metatiles.bin`. Every bit is decoded,
+    /// so this is exactly the value that was read.
+    pub fn raw(&self) -> u16 {
+        self.tile_id
+            | u16::from(self.flip_horizontal) << 10
+            | u16::from(self.flip_vertical) << 11
+            | u16::from(self.palette_number) << 12
+    }
+}
+
 impl From<u16> for TileData {
     fn from(value: u16) -> Self {
         TileData {
@@ -221,115 +1437,339 @@ impl From<u16> for TileData {
     }
 }
 
+#[cfg(feature = "fs")]
 fn parse_metatile_files(
     metatiles_path: impl AsRef<Path>,
     attributes_path: impl AsRef<Path>,
+    game_version: GameVersion,
 ) -> io::Result<Vec<Metatile>> {
-    let mut metatile_file = std::fs::File::open(metatiles_path)?;
-    let mut metatile_raw_data = vec![];
-    metatile_file.read_to_end(&mut metatile_raw_data)?;
+    let metatile_raw_data = gzip::read(&metatiles_path)?;
+    let attrs_raw_data = match gzip::read(&attributes_path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            // Zeroed attributes decode as middle-top metatiles with no behavior.
+            log::warn!(
+                "{} not found, treating every metatile as {}",
+                attributes_path.as_ref().display(),
+                LayerType::MiddleTop
+            );
+            let metatile_count = metatile_raw_data.len().div_ceil(METATILE_SIZE);
+            vec![0; metatile_count * game_version.attribute_size()]
+        }
+        Err(err) => return Err(err),
+    };
 
-    let mut attributes_file = std::fs::File::open(attributes_path)?;
-    let mut attrs_raw_data = vec![];
-    attributes_file.read_to_end(&mut attrs_raw_data)?;
+    parse_metatile_data(&metatile_raw_data, &attrs_raw_data, game_version).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "{} and {}: {err}",
+                metatiles_path.as_ref().display(),
+                attributes_path.as_ref().display()
+            ),
+        )
+    })
+}
 
-    const METATILE_SIZE: usize = 8 * 2;
-    if metatile_raw_data.len() % METATILE_SIZE != 0 {
-        return Err(io::ErrorKind::InvalidData.into());
+fn parse_metatile_data(
+    metatile_raw_data: &[u8],
+    attrs_raw_data: &[u8],
+    game_version: GameVersion,
+) -> io::Result<Vec<Metatile>> {
+    let attribute_size = game_version.attribute_size();
+    if !metatile_raw_data.len().is_multiple_of(METATILE_SIZE) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "metatile data has length {}, expected a multiple of {METATILE_SIZE}",
+                metatile_raw_data.len()
+            ),
+        ));
     }
-    const ATTR_SIZE: usize = 4;
-    if attrs_raw_data.len() % ATTR_SIZE != 0 {
-        return Err(io::ErrorKind::InvalidData.into());
+    if !attrs_raw_data.len().is_multiple_of(attribute_size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "metatile attributes have length {}, expected a multiple of {attribute_size}",
+                attrs_raw_data.len()
+            ),
+        ));
+    }
+    let metatile_count = metatile_raw_data.len() / METATILE_SIZE;
+    let attribute_count = attrs_raw_data.len() / attribute_size;
+    if attribute_count < metatile_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("only {attribute_count} metatile attributes for {metatile_count} metatiles"),
+        ));
     }
 
     let mut metatiles = vec![];
-    let mut cursor = io::Cursor::new(&metatile_raw_data);
-    let mut attr_cursor = io::Cursor::new(&attrs_raw_data);
-    while cursor.position() != metatile_raw_data.len() as u64 {
-        let attr_data = attr_cursor.read_u32::<LittleEndian>()?;
-        let attr = MetatileAttributes::from(attr_data);
-
-        let tile_data = (0..8)
-            .map(|_| {
-                let tile = cursor.read_u16::<LittleEndian>()?;
-                Ok(TileData::from(tile))
-            })
-            .collect::<io::Result<Vec<_>>>()?;
-        metatiles.push(Metatile {
-            tiles: tile_data.try_into().unwrap(),
-            _attributes: attr,
-        });
+    let mut attr_cursor = io::Cursor::new(attrs_raw_data);
+    for tiles in metatile_raw_data.chunks_exact(METATILE_SIZE) {
+        let attr = match game_version {
+            GameVersion::FireRedLeafGreen => {
+                MetatileAttributes::from(attr_cursor.read_u32::<LittleEndian>()?)
+            }
+            GameVersion::RubySapphireEmerald => {
+                MetatileAttributes::from_rse(attr_cursor.read_u16::<LittleEndian>()?)
+            }
+        };
+        if let LayerType::Unknown(value) = attr.layer_type {
+            log::warn!(
+                "Metatile {} has unknown layer type {value}, drawing it as {}",
+                metatiles.len(),
+                LayerType::MiddleTop
+            );
+        }
+        metatiles.push(Metatile::from_parts(tiles, attr));
     }
 
     Ok(metatiles)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TilesetImage {
     tileset_data: Vec<u8>,
     tile_width: usize,
     tile_height: usize,
+    /// Which palette indices are see-through, from the png's tRNS chunk if it has one
+    transparent: [bool; 16],
+    /// Each tile's palette indices, unpacked the first time the tile is used
+    tile_cache: Vec<OnceCell<GrayImage>>,
+    /// The colors of a sheet which wasn't saved as indexed, in the order they were indexed
+    derived_palette: Option<Palette>,
 }
 
+/// Tile sheets compare by their pixel data only, the unpacked tile cache doesn't affect
+/// equality.
+impl PartialEq for TilesetImage {
+    fn eq(&self, other: &Self) -> bool {
+        self.tileset_data == other.tileset_data
+            && self.tile_width == other.tile_width
+            && self.tile_height == other.tile_height
+            && self.transparent == other.transparent
+            && self.derived_palette == other.derived_palette
+    }
+}
+
+impl Eq for TilesetImage {}
+
+/// Without a tRNS chunk only palette index 0 is transparent, as on the GBA.
+const DEFAULT_TRANSPARENCY: [bool; 16] = {
+    let mut transparent = [false; 16];
+    transparent[0] = true;
+    transparent
+};
+
 impl TilesetImage {
-    fn get_tile(&self, tile_id: usize) -> Option<GrayImage> {
-        if tile_id < self.tile_width * self.tile_height {
-            let mut tile_image = ImageBuffer::new(8, 8);
-
-            let tile_x = tile_id % self.tile_width;
-            let tile_y = tile_id / self.tile_width;
-            for row in 0..8 {
-                for col in 0..8 {
-                    const PIXELS_PER_BYTE: usize = 2;
-                    const TILE_PIXEL_DIM: usize = 8;
-                    let tileset_pixel_x = tile_x * TILE_PIXEL_DIM + col;
-                    let tileset_pixel_y = tile_y * TILE_PIXEL_DIM + row;
-                    let offset = (tileset_pixel_y * (self.tile_width * TILE_PIXEL_DIM)
-                        + tileset_pixel_x)
-                        / PIXELS_PER_BYTE;
-                    let data = if col % 2 == 0 {
-                        // Odd column
-                        self.tileset_data[offset] >> 4
-                    } else {
-                        self.tileset_data[offset] & 0xf
-                    };
-                    let pixel: &mut Luma<u8> = tile_image.get_pixel_mut(col as u32, row as u32);
-                    pixel.0 = [data];
-                }
-            }
+    fn is_transparent(&self, index: u8) -> bool {
+        self.transparent[usize::from(index)]
+    }
 
-            Some(tile_image)
+    /// Width of the sheet, in tiles.
+    pub fn tiles_wide(&self) -> usize {
+        self.tile_width
+    }
+
+    /// Height of the sheet, in tiles.
+    pub fn tiles_high(&self) -> usize {
+        self.tile_height
+    }
+
+    /// Number of tiles in the sheet. Tile ids run from 0 up to this, row by row.
+    pub fn tile_count(&self) -> usize {
+        self.tile_width * self.tile_height
+    }
+
+    fn tile_pixel_origin(&self, tile_id: usize) -> Option<(u32, u32)> {
+        if tile_id < self.tile_count() {
+            let tile_x = (tile_id % self.tile_width) as u32;
+            let tile_y = (tile_id / self.tile_width) as u32;
+            Some((tile_x * TILE_DIMENSION, tile_y * TILE_DIMENSION))
         } else {
-            log::error!(
-                "Out of range: {tile_id}, max: {}",
-                self.tile_height * self.tile_width
-            );
+            log::error!("Out of range: {tile_id}, max: {}", self.tile_count());
             None
         }
     }
+
+    fn index_at(&self, x: usize, y: usize) -> u8 {
+        const PIXELS_PER_BYTE: usize = 2;
+        let row_pixels = self.tile_width * TILE_DIMENSION as usize;
+        let offset = (y * row_pixels + x) / PIXELS_PER_BYTE;
+        if x.is_multiple_of(PIXELS_PER_BYTE) {
+            // Even pixels are stored in the high nibble
+            self.tileset_data[offset] >> 4
+        } else {
+            self.tileset_data[offset] & 0xf
+        }
+    }
+
+    fn get_tile(&self, tile_id: usize) -> Option<&GrayImage> {
+        let (tile_x, tile_y) = self.tile_pixel_origin(tile_id)?;
+        Some(self.tile_cache[tile_id].get_or_init(|| {
+            let mut tile_image = ImageBuffer::new(TILE_DIMENSION, TILE_DIMENSION);
+            for row in 0..TILE_DIMENSION {
+                for col in 0..TILE_DIMENSION {
+                    let data = self.index_at((tile_x + col) as usize, (tile_y + row) as usize);
+                    let pixel: &mut Luma<u8> = tile_image.get_pixel_mut(col, row);
+                    pixel.0 = [data];
+                }
+            }
+            tile_image
+        }))
+    }
+
+    fn to_rgba(&self, palette: &Palette) -> RgbaImage {
+        let width = self.tile_width as u32 * TILE_DIMENSION;
+        let height = self.tile_height as u32 * TILE_DIMENSION;
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let index = self.index_at(x as usize, y as usize);
+            let (r, g, b) = *palette.get(index as usize);
+            let alpha = if self.is_transparent(index) { 0 } else { 255 };
+            Rgba([r, g, b, alpha])
+        })
+    }
 }
 
-fn parse_tileset_png(path: impl AsRef<Path>) -> io::Result<TilesetImage> {
-    let mut decoder = Decoder::new(std::fs::File::open(path)?);
+fn parse_tileset_png(reader: impl Read) -> io::Result<TilesetImage> {
+    let mut decoder = Decoder::new(reader);
     let info = decoder.read_header_info()?;
-    assert_eq!(info.bit_depth, png::BitDepth::Four);
-    assert_eq!(info.width % 8, 0);
-    assert_eq!(info.height % 8, 0);
-    assert_eq!(info.color_type, png::ColorType::Indexed);
+    if info.color_type != png::ColorType::Indexed || info.bit_depth != png::BitDepth::Four {
+        return index_tileset_png(decoder);
+    }
+    check_sheet_dimensions(info.width, info.height)?;
 
-    let tile_width = info.width as usize / 8;
-    let tile_height = info.height as usize / 8;
+    let tile_width = (info.width / TILE_DIMENSION) as usize;
+    let tile_height = (info.height / TILE_DIMENSION) as usize;
     let mut reader = decoder.read_info()?;
+    let transparent = match &reader.info().trns {
+        Some(trns) => {
+            let mut transparent = [false; 16];
+            for (index, alpha) in trns.iter().take(16).enumerate() {
+                transparent[index] = *alpha == 0;
+            }
+            transparent
+        }
+        None => DEFAULT_TRANSPARENCY,
+    };
     let mut tileset_data = vec![0; reader.output_buffer_size()];
     let info = reader.next_frame(&mut tileset_data)?;
     tileset_data.resize(info.buffer_size(), 0);
-    assert_eq!(tileset_data.len(), (info.width * info.height / 2) as usize);
-
     // In these tile images, each pixel is 4 bits, so each byte will contain 2 pixels of data
+    let expected_len = info.width as usize * info.height as usize / 2;
+    if tileset_data.len() != expected_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "tile sheet decoded to {} bytes, expected {expected_len}",
+                tileset_data.len()
+            ),
+        ));
+    }
+
+    Ok(TilesetImage {
+        tileset_data,
+        tile_width,
+        tile_height,
+        transparent,
+        tile_cache: vec![OnceCell::new(); tile_width * tile_height],
+        derived_palette: None,
+    })
+}
+
+fn check_sheet_dimensions(width: u32, height: u32) -> io::Result<()> {
+    if !width.is_multiple_of(TILE_DIMENSION) || !height.is_multiple_of(TILE_DIMENSION) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("tile sheet is {width}x{height}, expected multiples of {TILE_DIMENSION}"),
+        ));
+    }
+    Ok(())
+}
 
+/// Rebuilds 4bpp indices for a tile sheet that was saved as grayscale, RGB or some other
+/// non 4bpp format, provided it has at most 15 opaque colors. Index 0 is kept for fully
+/// transparent pixels, gray levels are indexed darkest first from 1 and other colors in
+/// order of first appearance. The colors become the sheet's derived palette, which its tiles
+/// are drawn with since the rebuilt indices won't line up with the tileset's palettes.
+fn index_tileset_png(mut decoder: Decoder<impl Read>) -> io::Result<TilesetImage> {
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer)?;
+    check_sheet_dimensions(frame.width, frame.height)?;
+
+    let grayscale = matches!(
+        frame.color_type,
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha
+    );
+    let samples = frame.color_type.samples();
+    let pixels: Vec<Option<(u8, u8, u8)>> = buffer[..frame.buffer_size()]
+        .chunks_exact(samples)
+        .map(|pixel| match pixel {
+            [gray] => Some((*gray, *gray, *gray)),
+            [_, 0] | [_, _, _, 0] => None,
+            [gray, _] => Some((*gray, *gray, *gray)),
+            [r, g, b] | [r, g, b, _] => Some((*r, *g, *b)),
+            _ => unreachable!("png pixels have 1 to 4 samples"),
+        })
+        .collect();
+
+    let mut colors: Vec<(u8, u8, u8)> = vec![];
+    for color in pixels.iter().flatten() {
+        if !colors.contains(color) {
+            colors.push(*color);
+        }
+    }
+    if grayscale {
+        colors.sort();
+    }
+    if colors.len() > 15 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "tile sheet isn't 4bpp indexed and has {} opaque colors, at most 15 can be \
+                 indexed",
+                colors.len()
+            ),
+        ));
+    }
+    log::warn!(
+        "tile sheet is a {:?} png rather than 4bpp indexed, indexed its {} colors and drawing \
+         it with them rather than the palettes",
+        frame.color_type,
+        colors.len()
+    );
+
+    let index_of = |pixel: &Option<(u8, u8, u8)>| match pixel {
+        None => 0,
+        Some(color) => (1 + colors.iter().position(|c| c == color).unwrap()) as u8,
+    };
+    let tileset_data = pixels
+        .chunks_exact(2)
+        .map(|pair| (index_of(&pair[0]) << 4) | index_of(&pair[1]))
+        .collect();
+
+    let mut derived = [(0, 0, 0); 16];
+    for (index, color) in colors.into_iter().enumerate() {
+        derived[1 + index] = color;
+    }
+    let tile_width = (frame.width / TILE_DIMENSION) as usize;
+    let tile_height = (frame.height / TILE_DIMENSION) as usize;
     Ok(TilesetImage {
         tileset_data,
         tile_width,
         tile_height,
+        transparent: DEFAULT_TRANSPARENCY,
+        tile_cache: vec![OnceCell::new(); tile_width * tile_height],
+        derived_palette: Some(Palette::from_colors(derived)),
     })
 }
+
+/// Indices at which `a` and `b` differ, including indices only one of them reaches.
+fn differing_indices<T: PartialEq>(a: &[T], b: &[T]) -> Vec<usize> {
+    (0..a.len().max(b.len()))
+        .filter(|&idx| a.get(idx) != b.get(idx))
+        .collect()
+}
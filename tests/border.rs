@@ -0,0 +1,45 @@
+mod common;
+
+use common::{gray_ramp_palette, solid_tiles_png, tileset, GAME};
+use frlg_render::map::Layout;
+use frlg_render::render::{render_border, render_layout, RenderOptions};
+use frlg_render::tileset::LayoutTileset;
+
+/// The primary metatile is drawn in color 1 and the secondary metatile in color 2, each
+/// from the first tile of its own sheet.
+fn two_color_tileset() -> LayoutTileset {
+    let secondary_tile = GAME.primary_tile_count() as u16;
+    LayoutTileset::from_tilesets(
+        tileset(&[[0; 8]], &solid_tiles_png(&[1]), vec![gray_ramp_palette()]),
+        tileset(
+            &[[secondary_tile; 8]],
+            &solid_tiles_png(&[2]),
+            vec![gray_ramp_palette()],
+        ),
+        GAME,
+    )
+}
+
+fn cell(metatile_id: usize) -> [u8; 2] {
+    (metatile_id as u16).to_le_bytes()
+}
+
+#[test]
+fn border_metatiles_resolve_through_the_secondary_tileset() {
+    let secondary = GAME.primary_metatile_count();
+    let border = [cell(0), cell(secondary), cell(secondary), cell(0)].concat();
+    let layout = Layout::from_bytes(1, 1, &cell(secondary), &border).unwrap();
+    let tileset = two_color_tileset();
+    let options = RenderOptions::default();
+
+    let map = render_layout(&layout, &tileset, &options)
+        .unwrap()
+        .to_rgb8();
+    let border = render_border(&layout, &tileset, &options).unwrap();
+    assert_eq!(border.dimensions(), (32, 32));
+    assert_eq!(map.get_pixel(0, 0).0, [2, 2, 2]);
+    assert_eq!(border.get_pixel(0, 0).0, [1, 1, 1]);
+    assert_eq!(border.get_pixel(16, 0).0, [2, 2, 2]);
+    assert_eq!(border.get_pixel(0, 16).0, [2, 2, 2]);
+    assert_eq!(border.get_pixel(16, 16).0, [1, 1, 1]);
+}
@@ -0,0 +1,92 @@
+//! Fixtures shared by the integration tests.
+// Each test file is its own crate and only uses some of these
+#![allow(dead_code)]
+
+use frlg_render::palette::Palette;
+use frlg_render::tileset::{GameVersion, LayoutTileset, Tileset};
+
+pub const GAME: GameVersion = GameVersion::FireRedLeafGreen;
+
+/// The 64 palette indices of one 8x8 tile, row by row.
+pub type Tile = [u8; 64];
+
+/// Encodes `data`, already packed as the png expects for `color` and `depth`.
+pub fn encode_png(
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut png_bytes = vec![];
+    let mut encoder = png::Encoder::new(&mut png_bytes, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    if color == png::ColorType::Indexed {
+        encoder.set_palette(vec![0; 16 * 3]);
+    }
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(data)
+        .unwrap();
+    png_bytes
+}
+
+/// A 4bpp indexed tile sheet with `tiles` side by side in a single row.
+pub fn tiles_png(tiles: &[Tile]) -> Vec<u8> {
+    let mut pixels = vec![];
+    for row in 0..8 {
+        for tile in tiles {
+            let row = &tile[row * 8..row * 8 + 8];
+            pixels.extend(row.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]));
+        }
+    }
+    encode_png(
+        8 * tiles.len() as u32,
+        8,
+        png::ColorType::Indexed,
+        png::BitDepth::Four,
+        &pixels,
+    )
+}
+
+/// An 8x8 tile per entry of `colors`, side by side, each filled with that palette index.
+pub fn solid_tiles_png(colors: &[u8]) -> Vec<u8> {
+    tiles_png(&colors.iter().map(|color| [*color; 64]).collect::<Vec<_>>())
+}
+
+/// A tile of index 0 apart from its top-left pixel, which is index 1.
+pub fn marker_tile() -> Tile {
+    let mut tile = [0; 64];
+    tile[0] = 1;
+    tile
+}
+
+/// Palette index `n` is the gray `(n, n, n)`, so a pixel's color names the index it used.
+pub fn gray_ramp_palette() -> Palette {
+    Palette::gradient((1, 1, 1), (15, 15, 15))
+}
+
+/// A tileset of `metatiles`, each given as the raw entries of its 8 tiles, drawn from the
+/// tile sheet `tiles_png` with `palettes`. Every metatile has zeroed attributes.
+pub fn tileset(metatiles: &[[u16; 8]], tiles_png: &[u8], palettes: Vec<Palette>) -> Tileset {
+    let metatile_bytes = metatiles
+        .iter()
+        .flatten()
+        .flat_map(|tile| tile.to_le_bytes())
+        .collect::<Vec<_>>();
+    Tileset::from_bytes(
+        &metatile_bytes,
+        &vec![0; 4 * metatiles.len()],
+        tiles_png,
+        palettes,
+        GAME,
+    )
+    .unwrap()
+}
+
+/// Uses `tileset` as both the primary and the secondary tileset.
+pub fn layout_tileset(tileset: Tileset) -> LayoutTileset {
+    LayoutTileset::from_tilesets(tileset.clone(), tileset, GAME)
+}
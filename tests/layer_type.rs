@@ -0,0 +1,24 @@
+use frlg_render::tileset::LayerType;
+
+#[test]
+fn layer_types_round_trip_through_their_names() {
+    let layer_types = [
+        LayerType::MiddleTop,
+        LayerType::BottomMiddle,
+        LayerType::BottomTop,
+        LayerType::Unknown(3),
+        LayerType::Unknown(255),
+    ];
+    for layer_type in layer_types {
+        assert_eq!(layer_type.to_string().parse(), Ok(layer_type));
+    }
+}
+
+#[test]
+fn bare_numbers_parse_as_raw_values() {
+    assert_eq!("0".parse(), Ok(LayerType::MiddleTop));
+    assert_eq!("2".parse(), Ok(LayerType::BottomTop));
+    assert_eq!("7".parse(), Ok(LayerType::Unknown(7)));
+    assert!("unknown (256)".parse::<LayerType>().is_err());
+    assert!("top".parse::<LayerType>().is_err());
+}
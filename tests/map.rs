@@ -0,0 +1,69 @@
+use frlg_render::map::{Layout, MapMetatileData, MetatileId};
+use std::io;
+
+fn cells(cells: &[MapMetatileData]) -> Vec<u8> {
+    cells
+        .iter()
+        .flat_map(|cell| cell.raw().to_le_bytes())
+        .collect()
+}
+
+#[test]
+fn shifted_differences_only_compare_metatile_ids() {
+    let cell = |metatile_id, collision, elevation| {
+        MapMetatileData::new(metatile_id, collision, elevation).unwrap()
+    };
+    let map = cells(&[cell(1, 0, 3), cell(1, 1, 4), cell(2, 0, 3)]);
+    let layout = Layout::from_bytes(3, 1, &map, &[0; 8]).unwrap();
+
+    assert_eq!(layout.shifted_differences(0, 1), vec![(0, 1)]);
+}
+
+#[test]
+fn cells_round_trip_through_their_packed_value() {
+    for (metatile_id, collision, elevation) in [(0, 0, 0), (0x3ff, 3, 15), (0x123, 2, 9)] {
+        let cell = MapMetatileData::new(metatile_id, collision, elevation).unwrap();
+        let unpacked = MapMetatileData::from(cell.raw());
+        assert_eq!(unpacked, cell);
+        assert_eq!(
+            (
+                unpacked.metatile_id(),
+                unpacked.collision(),
+                unpacked.elevation()
+            ),
+            (metatile_id, collision, elevation)
+        );
+    }
+}
+
+#[test]
+fn raw_values_round_trip_through_cells() {
+    for raw in [0, 0x03ff, 0x0c00, 0xf000, 0xffff, 0x9a5c] {
+        assert_eq!(MapMetatileData::from(raw).raw(), raw);
+    }
+}
+
+#[test]
+fn out_of_range_fields_are_rejected() {
+    assert!(MetatileId::new(0x3ff).is_ok());
+    for err in [
+        MetatileId::new(0x400).unwrap_err(),
+        MetatileId::try_from(u16::MAX).unwrap_err(),
+        MapMetatileData::new(0x400, 0, 0).unwrap_err(),
+        MapMetatileData::new(0, 4, 0).unwrap_err(),
+        MapMetatileData::new(0, 0, 16).unwrap_err(),
+    ] {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn metatile_ids_deserialize_with_the_same_checks() {
+    let id: MetatileId = serde_json::from_str("1023").unwrap();
+    assert_eq!(u16::from(id), 1023);
+    assert!(serde_json::from_str::<MetatileId>("1024").is_err());
+    assert!(serde_json::from_str::<MapMetatileData>(
+        r#"{"metatile_id": 1024, "collision": 0, "elevation": 0}"#
+    )
+    .is_err());
+}
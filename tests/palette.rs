@@ -0,0 +1,79 @@
+#[cfg(feature = "fs")]
+use frlg_render::palette::parse_all_palettes;
+use frlg_render::palette::{parse_palette_contents, Palette};
+
+fn jasc_palette(line_ending: &str) -> String {
+    let mut lines = vec!["JASC-PAL".to_string(), "0100".to_string(), "16".to_string()];
+    lines.extend((0..16).map(|index| format!("{} {} {}", index, index * 2, index * 3)));
+    lines.join(line_ending) + line_ending
+}
+
+fn expected_palette() -> Palette {
+    Palette::from_colors(std::array::from_fn(|index| {
+        let index = index as u8;
+        (index, index * 2, index * 3)
+    }))
+}
+
+#[test]
+fn parses_lf_palette() {
+    let palette = parse_palette_contents(&jasc_palette("\n")).unwrap();
+    assert_eq!(palette, expected_palette());
+}
+
+#[test]
+fn parses_crlf_palette_with_byte_order_mark() {
+    let contents = format!("\u{feff}{}", jasc_palette("\r\n"));
+    let palette = parse_palette_contents(&contents).unwrap();
+    assert_eq!(palette, expected_palette());
+}
+
+#[test]
+fn ignores_whitespace_around_lines() {
+    let contents = jasc_palette(" \r\n").replace("JASC-PAL", "  JASC-PAL");
+    let palette = parse_palette_contents(&contents).unwrap();
+    assert_eq!(palette, expected_palette());
+}
+
+#[test]
+fn rejects_malformed_entries() {
+    let valid = jasc_palette("\n");
+    for contents in [
+        valid.replace("3 6 9", "3 6 256"),
+        valid.replace("3 6 9", "3 six 9"),
+        valid.replace("3 6 9", "3 6"),
+        valid.replace("3 6 9", "3 6 9 12"),
+        valid.lines().take(10).collect::<Vec<_>>().join("\n"),
+    ] {
+        let err = parse_palette_contents(&contents).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{contents:?}");
+    }
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn skips_palette_files_not_named_for_a_slot() {
+    let dir = std::env::temp_dir().join(format!("frlg-render-palettes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["01.pal", "00.pal", "custom.pal", "00_backup.pal"] {
+        std::fs::write(dir.join(name), jasc_palette("\n")).unwrap();
+    }
+
+    let palettes = parse_all_palettes(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(palettes.unwrap(), vec![expected_palette(); 2]);
+}
+
+#[test]
+fn rejects_a_bad_header() {
+    let valid = jasc_palette("\n");
+    for contents in [
+        valid.replace("JASC-PAL", "JASC"),
+        valid.replace("0100", "0200"),
+        valid.replacen("\n16\n", "\n15\n", 1),
+        String::new(),
+    ] {
+        let err = parse_palette_contents(&contents).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{contents:?}");
+    }
+}
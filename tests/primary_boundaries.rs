@@ -0,0 +1,45 @@
+mod common;
+
+use common::{gray_ramp_palette, solid_tiles_png, tileset, GAME};
+use frlg_render::tileset::LayoutTileset;
+
+const PRIMARY_METATILES: usize = 2;
+const PRIMARY_TILES: usize = 3;
+
+/// Both tilesets have two metatiles, the first drawing the last primary tile and the second
+/// the first secondary tile on every tile of both layers. Primary tiles are colored 1-3 and
+/// secondary tiles 4-5.
+fn boundary_tileset() -> LayoutTileset {
+    let metatiles = [[PRIMARY_TILES as u16 - 1; 8], [PRIMARY_TILES as u16; 8]];
+    let tileset = |colors: &[u8]| {
+        tileset(
+            &metatiles,
+            &solid_tiles_png(colors),
+            vec![gray_ramp_palette()],
+        )
+    };
+    LayoutTileset::from_tilesets(tileset(&[1, 2, 3]), tileset(&[4, 5]), GAME)
+        .with_primary_counts(PRIMARY_METATILES, PRIMARY_TILES)
+}
+
+fn metatile_color(tileset: &LayoutTileset, metatile_id: usize) -> Option<u8> {
+    let image = tileset.get_metatile_image(metatile_id as u16).ok()?;
+    Some(image.get_pixel(0, 0).0[0])
+}
+
+#[test]
+fn metatile_ids_split_at_the_primary_metatile_count() {
+    let tileset = boundary_tileset();
+    assert_eq!(metatile_color(&tileset, PRIMARY_METATILES - 1), Some(4));
+    assert_eq!(metatile_color(&tileset, PRIMARY_METATILES), Some(3));
+    assert_eq!(metatile_color(&tileset, PRIMARY_METATILES + 2), None);
+}
+
+#[test]
+fn tile_ids_split_at_the_primary_tile_count() {
+    let tileset = boundary_tileset();
+    assert_eq!(metatile_color(&tileset, 0), Some(3));
+    assert_eq!(metatile_color(&tileset, 1), Some(4));
+    assert_eq!(metatile_color(&tileset, PRIMARY_METATILES), Some(3));
+    assert_eq!(metatile_color(&tileset, PRIMARY_METATILES + 1), Some(4));
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{gray_ramp_palette, layout_tileset, solid_tiles_png, tileset};
+use frlg_render::map::Layout;
+use frlg_render::render::{
+    render_border, render_layout, render_layout_with_unresolved, render_to_png_bytes, Color,
+    PngCompression, Region, RenderOptions,
+};
+use frlg_render::tileset::LayoutTileset;
+use std::io;
+
+/// Both tilesets have a single metatile drawn in `color`.
+fn solid_layout_tileset(color: u8) -> LayoutTileset {
+    layout_tileset(tileset(
+        &[[0; 8]],
+        &solid_tiles_png(&[color]),
+        vec![gray_ramp_palette()],
+    ))
+}
+
+#[test]
+fn region_contains_near_the_end_of_u32() {
+    let region = Region {
+        x: u32::MAX - 1,
+        y: u32::MAX - 1,
+        width: 2,
+        height: 2,
+    };
+    assert!(region.contains(u32::MAX, u32::MAX));
+    assert!(region.contains(u32::MAX - 1, u32::MAX - 1));
+    assert!(!region.contains(0, 0));
+    assert!(!region.contains(u32::MAX - 2, u32::MAX));
+}
+
+#[test]
+fn oversized_renders_fail_instead_of_overflowing() {
+    let layout = Layout::from_bytes(1, 1, &[0; 2], &[0; 8]).unwrap();
+    let tileset = solid_layout_tileset(1);
+    let options = RenderOptions::builder().scale(u32::MAX).build();
+
+    let err = render_layout(&layout, &tileset, &options).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = render_border(&layout, &tileset, &options).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = tileset.render_grid(&[0; 2], 1 << 30).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn transparent_pixels_show_the_background() {
+    let layout = Layout::from_bytes(1, 1, &[0; 2], &[0; 8]).unwrap();
+    let options = RenderOptions::builder()
+        .background(Color { r: 9, g: 8, b: 7 })
+        .build();
+
+    let opaque = render_layout(&layout, &solid_layout_tileset(1), &options).unwrap();
+    assert_eq!(opaque.to_rgb8().get_pixel(0, 0).0, [1, 1, 1]);
+    let transparent = render_layout(&layout, &solid_layout_tileset(0), &options).unwrap();
+    assert_eq!(transparent.to_rgb8().get_pixel(0, 0).0, [9, 8, 7]);
+    let border = render_border(&layout, &solid_layout_tileset(0), &options).unwrap();
+    assert_eq!(border.get_pixel(0, 0).0, [9, 8, 7]);
+}
+
+#[test]
+fn alpha_checker_fills_transparent_pixels_with_a_checkerboard() {
+    let layout = Layout::from_bytes(1, 1, &[0; 2], &[0; 8]).unwrap();
+    let options = RenderOptions::builder()
+        .background(Color { r: 9, g: 8, b: 7 })
+        .alpha_checker(true)
+        .build();
+
+    let transparent = render_layout(&layout, &solid_layout_tileset(0), &options)
+        .unwrap()
+        .to_rgb8();
+    let light = transparent.get_pixel(0, 0).0;
+    let dark = transparent.get_pixel(4, 0).0;
+    assert_ne!(light, dark);
+    assert_eq!(transparent.get_pixel(4, 4).0, light);
+    assert!(![light, dark].contains(&[9, 8, 7]));
+    let opaque = render_layout(&layout, &solid_layout_tileset(1), &options).unwrap();
+    assert_eq!(opaque.to_rgb8().get_pixel(0, 0).0, [1, 1, 1]);
+}
+
+#[test]
+fn renders_count_the_cells_they_could_not_draw() {
+    // Metatile 1 doesn't exist in either tileset
+    let layout = Layout::from_bytes(3, 1, &[0, 0, 1, 0, 1, 0], &[0; 8]).unwrap();
+    let options = RenderOptions::builder().scale(2).build();
+
+    let (image, unresolved) =
+        render_layout_with_unresolved(&layout, &solid_layout_tileset(1), &options).unwrap();
+    assert_eq!(unresolved, 2);
+    assert_eq!((image.width(), image.height()), (96, 32));
+}
+
+#[test]
+fn strict_renders_only_check_the_layers_drawn() {
+    // The top layer refers to a tile past the end of the one tile sheet
+    let tileset = layout_tileset(tileset(
+        &[[0, 0, 0, 0, 5, 5, 5, 5]],
+        &solid_tiles_png(&[1]),
+        vec![gray_ramp_palette()],
+    ));
+    let layout = Layout::from_bytes(1, 1, &[0; 2], &[0; 8]).unwrap();
+
+    let bottom_only = RenderOptions::builder()
+        .strict(true)
+        .bottom_only(true)
+        .build();
+    assert!(render_layout(&layout, &tileset, &bottom_only).is_ok());
+    let both_layers = RenderOptions::builder().strict(true).build();
+    let err = render_layout(&layout, &tileset, &both_layers).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn png_bytes_use_the_requested_compression() {
+    let layout = Layout::from_bytes(8, 8, &[0; 128], &[0; 8]).unwrap();
+    let tileset = solid_layout_tileset(1);
+    let options = RenderOptions::default();
+
+    let fast = render_to_png_bytes(&layout, &tileset, &options, PngCompression::Fast).unwrap();
+    let best = render_to_png_bytes(&layout, &tileset, &options, PngCompression::Best).unwrap();
+    assert!(best.len() < fast.len());
+    let decode = |png_bytes: &[u8]| image::load_from_memory(png_bytes).unwrap().to_rgb8();
+    assert_eq!(decode(&fast), decode(&best));
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "rom")]
+
+use frlg_render::rom::Rom;
+use std::io;
+
+const ROM_BASE_ADDRESS: u32 = 0x0800_0000;
+const MAP_OFFSET: u32 = 0x20;
+const BORDER_OFFSET: u32 = 0x24;
+
+/// A layout header at offset 0 describing a 2x1 layout with a 2x2 border, followed by its
+/// blockdata and border.
+fn synthetic_rom(width: u32, height: u32) -> Vec<u8> {
+    let mut rom = vec![];
+    rom.extend(width.to_le_bytes());
+    rom.extend(height.to_le_bytes());
+    rom.extend((ROM_BASE_ADDRESS + BORDER_OFFSET).to_le_bytes());
+    rom.extend((ROM_BASE_ADDRESS + MAP_OFFSET).to_le_bytes());
+    rom.extend([0; 8]);
+    rom.extend([2, 2, 0, 0]);
+    rom.resize(MAP_OFFSET as usize, 0);
+    rom.extend([1u16, 2].into_iter().flat_map(u16::to_le_bytes));
+    rom.extend([3u16, 4, 5, 6].into_iter().flat_map(u16::to_le_bytes));
+    rom
+}
+
+#[test]
+fn loads_a_layout_from_its_header() {
+    let rom = Rom::new(synthetic_rom(2, 1));
+    let header = rom.layout_header(0).unwrap();
+    assert_eq!((header.width, header.height), (2, 1));
+    assert_eq!((header.border_width, header.border_height), (2, 2));
+
+    let layout = rom.load_layout(&header).unwrap();
+    let cells = (0..2)
+        .map(|col| layout.get_metatile(0, col).unwrap().metatile_id())
+        .collect::<Vec<_>>();
+    assert_eq!(cells, [1, 2]);
+}
+
+#[test]
+fn oversized_layout_headers_are_invalid_data() {
+    let rom = Rom::new(synthetic_rom(u32::MAX, u32::MAX));
+    let header = rom.layout_header(0).unwrap();
+    let err = rom.load_layout(&header).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn layouts_past_the_end_of_the_rom_fail() {
+    let rom = Rom::new(synthetic_rom(100, 100));
+    let header = rom.layout_header(0).unwrap();
+    let err = rom.load_layout(&header).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn layout_table_offsets_which_overflow_are_invalid_data() {
+    let rom = Rom::new(synthetic_rom(2, 1));
+    for (table_offset, index) in [(0, usize::MAX), (usize::MAX, 1)] {
+        let err = rom
+            .layout_header_from_table(table_offset, index)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{layout_tileset, marker_tile, tiles_png, tileset};
+use frlg_render::palette::parse_palette_contents;
+use frlg_render::tileset::LayoutTileset;
+
+const FLIP_HORIZONTAL: u16 = 0x400;
+const FLIP_VERTICAL: u16 = 0x800;
+const PALETTE_1: u16 = 0x1000;
+
+fn black_and_white_palette() -> String {
+    let mut contents = "JASC-PAL\n0100\n16\n0 0 0\n255 255 255\n".to_string();
+    contents.push_str(&"0 0 0\n".repeat(14));
+    contents
+}
+
+/// One metatile whose bottom layer draws a marker tile, whose top-left pixel is index 1, once
+/// per flip combination and
+/// whose top layer is empty. With `mixed_palettes`, the right hand tiles use palette slot 1
+/// instead of 0, so the metatile can't be drawn from a single palette.
+fn flipped_marker_tileset(mixed_palettes: bool) -> LayoutTileset {
+    let palette = if mixed_palettes { PALETTE_1 } else { 0 };
+    let tiles = [
+        0,
+        FLIP_HORIZONTAL | palette,
+        FLIP_VERTICAL,
+        FLIP_HORIZONTAL | FLIP_VERTICAL | palette,
+        1,
+        1,
+        1,
+        1,
+    ];
+    let palette = parse_palette_contents(&black_and_white_palette()).unwrap();
+    layout_tileset(tileset(
+        &[tiles],
+        &tiles_png(&[marker_tile(), [0; 64]]),
+        vec![palette.clone(), palette],
+    ))
+}
+
+fn marker_pixels(tileset: &LayoutTileset) -> Vec<(u32, u32)> {
+    let image = tileset.get_metatile_image(0).unwrap();
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0 == [255, 255, 255])
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+#[test]
+fn flips_mirror_along_the_expected_axis() {
+    // Unflipped in the top-left quadrant, horizontal flip moves the marker to the right
+    // edge of the top-right quadrant, vertical to the bottom edge of the bottom-left one.
+    let expected = vec![(0, 0), (15, 0), (0, 15), (15, 15)];
+    assert_eq!(marker_pixels(&flipped_marker_tileset(false)), expected);
+
+    let mut prewarmed = flipped_marker_tileset(false);
+    prewarmed.prewarm().unwrap();
+    assert_eq!(marker_pixels(&prewarmed), expected);
+}
+
+#[test]
+fn flips_match_when_tiles_use_different_palettes() {
+    let expected = vec![(0, 0), (15, 0), (0, 15), (15, 15)];
+    assert_eq!(marker_pixels(&flipped_marker_tileset(true)), expected);
+
+    let mut prewarmed = flipped_marker_tileset(true);
+    prewarmed.prewarm().unwrap();
+    assert_eq!(marker_pixels(&prewarmed), expected);
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{
+    encode_png, gray_ramp_palette, layout_tileset, marker_tile, solid_tiles_png, tiles_png,
+    tileset, GAME,
+};
+use frlg_render::map::Layout;
+use frlg_render::render::{render_layout, Color, RenderOptions};
+use frlg_render::tileset::Tileset;
+use std::io;
+
+fn load_sheet(png_bytes: &[u8]) -> io::Result<Tileset> {
+    Tileset::from_bytes(
+        &[0; 16],
+        &[0; 4],
+        png_bytes,
+        vec![gray_ramp_palette()],
+        GAME,
+    )
+}
+
+/// An all zero png of the given format.
+fn blank_png(width: u32, height: u32, color: png::ColorType, depth: png::BitDepth) -> Vec<u8> {
+    let bits_per_pixel = color.samples() * depth as usize;
+    let row_bytes = (width as usize * bits_per_pixel).div_ceil(8);
+    encode_png(
+        width,
+        height,
+        color,
+        depth,
+        &vec![0; row_bytes * height as usize],
+    )
+}
+
+#[test]
+fn loads_an_indexed_sheet() {
+    let tileset = load_sheet(&solid_tiles_png(&[1, 2, 3])).unwrap();
+    assert_eq!(tileset.tile_count(), 3);
+}
+
+#[test]
+fn sheets_not_a_multiple_of_the_tile_size_are_invalid_data() {
+    for (color, depth) in [
+        (png::ColorType::Indexed, png::BitDepth::Four),
+        (png::ColorType::Grayscale, png::BitDepth::Eight),
+    ] {
+        let err = load_sheet(&blank_png(12, 8, color, depth)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{color:?}");
+    }
+}
+
+#[test]
+fn indexes_a_grayscale_sheet() {
+    let tileset = load_sheet(&blank_png(
+        16,
+        8,
+        png::ColorType::Grayscale,
+        png::BitDepth::Eight,
+    ));
+    assert_eq!(tileset.unwrap().tile_count(), 2);
+}
+
+#[test]
+fn index_zero_usage_only_counts_bottom_layer_tiles() {
+    // Tile 0 is mostly index 0, tile 1 is solid index 1
+    let sheet = tiles_png(&[marker_tile(), [1; 64]]);
+    let tileset = |metatile_tiles: [u16; 8]| {
+        layout_tileset(tileset(
+            &[metatile_tiles],
+            &sheet,
+            vec![gray_ramp_palette()],
+        ))
+    };
+
+    let in_top_layer = tileset([1, 1, 1, 1, 0, 0, 0, 0]);
+    assert_eq!(in_top_layer.index_zero_usage(), Vec::<u16>::new());
+    let in_bottom_layer = tileset([1, 0, 1, 1, 1, 1, 1, 1]);
+    assert_eq!(in_bottom_layer.index_zero_usage(), vec![0]);
+}
+
+#[test]
+fn rgb_sheets_are_drawn_in_their_own_colors() {
+    // Tile 0 is solid black, tile 1 solid red, with no transparent pixels
+    let pixels = (0..8)
+        .flat_map(|_| [[0, 0, 0]; 8].into_iter().chain([[200, 10, 10]; 8]))
+        .flatten()
+        .collect::<Vec<u8>>();
+    let sheet = encode_png(16, 8, png::ColorType::Rgb, png::BitDepth::Eight, &pixels);
+    let tileset = tileset(
+        &[[0, 1, 0, 1, 0, 1, 0, 1]],
+        &sheet,
+        vec![gray_ramp_palette()],
+    );
+    let derived = tileset.derived_palette().unwrap();
+    assert_eq!(*derived.get(1), (0, 0, 0));
+    assert_eq!(*derived.get(2), (200, 10, 10));
+
+    let layout = Layout::from_bytes(1, 1, &[0; 2], &[0; 8]).unwrap();
+    let options = RenderOptions::builder()
+        .background(Color { r: 9, g: 8, b: 7 })
+        .build();
+    let image = render_layout(&layout, &layout_tileset(tileset), &options)
+        .unwrap()
+        .to_rgb8();
+    // Black is opaque rather than taking the transparent index 0
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+    assert_eq!(image.get_pixel(8, 0).0, [200, 10, 10]);
+}